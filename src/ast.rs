@@ -1,84 +1,293 @@
+use std::{cell::Cell, rc::Rc};
+
+use serde::{Deserialize, Serialize};
+
 use crate::{
     error::LoxError,
+    span::Span,
     token::{Object, Token},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
 pub enum Expr {
+    Assign(AssignExpr),
     Binary(BinaryExpr),
+    Call(CallExpr),
+    Get(GetExpr),
     Grouping(GroupingExpr),
+    Interpolation(InterpolationExpr),
     Literal(LiteralExpr),
+    Logical(LogicalExpr),
+    Set(SetExpr),
+    Super(SuperExpr),
+    This(ThisExpr),
     Unary(UnaryExpr),
     Variable(VariableExpr),
 }
 
+/// Serializes to the JSON form described in the README. The resolver's
+/// `depth`s are left out, so a loaded tree has to be resolved again.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[allow(clippy::enum_variant_names)]
 pub enum Stmt {
+    BlockStmt(BlockStmt),
+    ClassStmt(ClassStmt),
     ExpressionStmt(ExpressionStmt),
+    FunctionStmt(FunctionStmt),
+    IfStmt(IfStmt),
     PrintStmt(PrintStmt),
+    ReturnStmt(ReturnStmt),
     VarStmt(VarStmt),
+    WhileStmt(WhileStmt),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Assign(n) => n.span,
+            Expr::Binary(n) => n.span,
+            Expr::Call(n) => n.span,
+            Expr::Get(n) => n.span,
+            Expr::Grouping(n) => n.span,
+            Expr::Interpolation(n) => n.span,
+            Expr::Literal(n) => n.span,
+            Expr::Logical(n) => n.span,
+            Expr::Set(n) => n.span,
+            Expr::Super(n) => n.span,
+            Expr::This(n) => n.span,
+            Expr::Unary(n) => n.span,
+            Expr::Variable(n) => n.span,
+        }
+    }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockStmt {
+    pub statements: Vec<Stmt>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClassStmt {
+    pub name: Token,
+    pub superclass: Option<VariableExpr>,
+    pub methods: Vec<FunctionStmt>,
+    /// The `///` comments written before the declaration, one line each
+    /// joined by '\n'. `FunctionStmt` and `VarStmt` keep theirs the same way.
+    #[serde(default)]
+    pub doc: Option<String>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ExpressionStmt {
     pub expression: Expr,
+    #[serde(default)]
+    pub span: Span,
 }
 
+/// The body is shared so that a `LoxFunction` created from this declaration
+/// can outlive the statement list it was parsed into.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionStmt {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Rc<Vec<Stmt>>,
+    #[serde(default)]
+    pub doc: Option<String>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IfStmt {
+    pub condition: Expr,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PrintStmt {
     pub expression: Expr,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnStmt {
+    pub keyword: Token,
+    pub value: Option<Expr>,
+    #[serde(default)]
+    pub span: Span,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
 pub struct VarStmt {
     pub name: Token,
     pub initializer: Option<Expr>,
+    #[serde(default)]
+    pub doc: Option<String>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WhileStmt {
+    pub condition: Expr,
+    pub body: Box<Stmt>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 pub trait ExprVisitor<T> {
+    fn visit_assign_expr(&self, assign_expr: &AssignExpr) -> Result<T, LoxError>;
     fn visit_binary_expr(&self, bin_expr: &BinaryExpr) -> Result<T, LoxError>;
+    fn visit_call_expr(&self, call_expr: &CallExpr) -> Result<T, LoxError>;
+    fn visit_get_expr(&self, get_expr: &GetExpr) -> Result<T, LoxError>;
     fn visit_grouping_expr(&self, gouping_expr: &GroupingExpr) -> Result<T, LoxError>;
+    fn visit_interpolation_expr(&self, expr: &InterpolationExpr) -> Result<T, LoxError>;
     fn visit_literal_expr(&self, literal_expr: &LiteralExpr) -> Result<T, LoxError>;
+    fn visit_logical_expr(&self, logical_expr: &LogicalExpr) -> Result<T, LoxError>;
+    fn visit_set_expr(&self, set_expr: &SetExpr) -> Result<T, LoxError>;
+    fn visit_super_expr(&self, super_expr: &SuperExpr) -> Result<T, LoxError>;
+    fn visit_this_expr(&self, this_expr: &ThisExpr) -> Result<T, LoxError>;
     fn visit_unary_expr(&self, unary_expr: &UnaryExpr) -> Result<T, LoxError>;
     fn visit_variable_expr(&self, unary_expr: &VariableExpr) -> Result<T, LoxError>;
 }
 
-pub trait StmtVisitor<T> {
-    fn visit_expression_stmt(&self, stmt: &ExpressionStmt) -> Result<T, LoxError>;
-    fn visit_print_stmt(&self, stmt: &PrintStmt) -> Result<T, LoxError>;
-    fn visit_var_stmt(&mut self, stmt: &VarStmt) -> Result<T, LoxError>;
+/// `E` lets the interpreter unwind through statements with more than just
+/// errors; see `interpreter::Unwind`.
+pub trait StmtVisitor<T, E = LoxError> {
+    fn visit_block_stmt(&self, stmt: &BlockStmt) -> Result<T, E>;
+    fn visit_class_stmt(&self, stmt: &ClassStmt) -> Result<T, E>;
+    fn visit_expression_stmt(&self, stmt: &ExpressionStmt) -> Result<T, E>;
+    fn visit_function_stmt(&self, stmt: &FunctionStmt) -> Result<T, E>;
+    fn visit_if_stmt(&self, stmt: &IfStmt) -> Result<T, E>;
+    fn visit_print_stmt(&self, stmt: &PrintStmt) -> Result<T, E>;
+    fn visit_return_stmt(&self, stmt: &ReturnStmt) -> Result<T, E>;
+    fn visit_var_stmt(&self, stmt: &VarStmt) -> Result<T, E>;
+    fn visit_while_stmt(&self, stmt: &WhileStmt) -> Result<T, E>;
+}
+
+impl BlockStmt {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
+        visitor.visit_block_stmt(self)
+    }
+}
+
+impl ClassStmt {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
+        visitor.visit_class_stmt(self)
+    }
 }
 
 impl ExpressionStmt {
-    pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
         visitor.visit_expression_stmt(self)
     }
 }
 
+impl FunctionStmt {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
+        visitor.visit_function_stmt(self)
+    }
+}
+
+impl IfStmt {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
+        visitor.visit_if_stmt(self)
+    }
+}
+
 impl PrintStmt {
-    pub fn accept<T>(&self, visitor: &dyn StmtVisitor<T>) -> Result<T, LoxError> {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
         visitor.visit_print_stmt(self)
     }
 }
 
+impl ReturnStmt {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
+        visitor.visit_return_stmt(self)
+    }
+}
+
 impl VarStmt {
-    pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
         visitor.visit_var_stmt(self)
     }
 }
 
+impl WhileStmt {
+    pub fn accept<T, E>(&self, visitor: &dyn StmtVisitor<T, E>) -> Result<T, E> {
+        visitor.visit_while_stmt(self)
+    }
+}
+
+impl AssignExpr {
+    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_assign_expr(self)
+    }
+}
+
 impl BinaryExpr {
     pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
         visitor.visit_binary_expr(self)
     }
 }
 
+impl CallExpr {
+    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_call_expr(self)
+    }
+}
+
+impl GetExpr {
+    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_get_expr(self)
+    }
+}
+
 impl GroupingExpr {
     pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
         visitor.visit_grouping_expr(self)
     }
 }
+impl InterpolationExpr {
+    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_interpolation_expr(self)
+    }
+}
 impl LiteralExpr {
     pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
         visitor.visit_literal_expr(self)
     }
 }
+impl LogicalExpr {
+    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_logical_expr(self)
+    }
+}
+impl SetExpr {
+    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_set_expr(self)
+    }
+}
+impl SuperExpr {
+    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_super_expr(self)
+    }
+}
+impl ThisExpr {
+    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_this_expr(self)
+    }
+}
 impl UnaryExpr {
     pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> Result<T, LoxError> {
         visitor.visit_unary_expr(self)
@@ -90,28 +299,110 @@ impl VariableExpr {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignExpr {
+    pub name: Token,
+    pub value: Box<Expr>,
+    /// Number of scopes between this reference and the variable's declaration,
+    /// filled in by the resolver. `None` means the variable is global.
+    #[serde(skip)]
+    pub depth: Cell<Option<usize>>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct BinaryExpr {
     pub left: Box<Expr>,
     pub right: Box<Expr>,
     pub operator: Token,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CallExpr {
+    pub callee: Box<Expr>,
+    pub paren: Token,
+    pub arguments: Vec<Expr>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetExpr {
+    pub object: Box<Expr>,
+    pub name: Token,
+    #[serde(default)]
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct GroupingExpr {
     pub expression: Box<Expr>,
+    #[serde(default)]
+    pub span: Span,
+}
+/// A string with `${...}` in it. The literal text and the interpolated
+/// expressions are stringified and joined in order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InterpolationExpr {
+    pub parts: Vec<Expr>,
+    #[serde(default)]
+    pub span: Span,
 }
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct LiteralExpr {
     pub value: Object,
+    #[serde(default)]
+    pub span: Span,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogicalExpr {
+    pub left: Box<Expr>,
+    pub operator: Token,
+    pub right: Box<Expr>,
+    #[serde(default)]
+    pub span: Span,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetExpr {
+    pub object: Box<Expr>,
+    pub name: Token,
+    pub value: Box<Expr>,
+    #[serde(default)]
+    pub span: Span,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SuperExpr {
+    pub keyword: Token,
+    pub method: Token,
+    #[serde(skip)]
+    pub depth: Cell<Option<usize>>,
+    #[serde(default)]
+    pub span: Span,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThisExpr {
+    pub keyword: Token,
+    #[serde(skip)]
+    pub depth: Cell<Option<usize>>,
+    #[serde(default)]
+    pub span: Span,
 }
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct UnaryExpr {
     pub operator: Token,
     pub right: Box<Expr>,
+    #[serde(default)]
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct VariableExpr {
     pub name: Token,
+    #[serde(skip)]
+    pub depth: Cell<Option<usize>>,
+    #[serde(default)]
+    pub span: Span,
 }
@@ -0,0 +1,214 @@
+use crate::{ast::*, error::LoxError, token::Object};
+
+/// Renders syntax trees in a Lisp-like form that spells out how the parser
+/// grouped everything, e.g. `(print (+ 1 (* 2 3)))`.
+pub struct AstPrinter;
+
+impl AstPrinter {
+    pub fn print_stmt(&self, stmt: &Stmt) -> String {
+        let result = match stmt {
+            Stmt::BlockStmt(n) => n.accept(self),
+            Stmt::ClassStmt(n) => n.accept(self),
+            Stmt::ExpressionStmt(n) => n.accept(self),
+            Stmt::FunctionStmt(n) => n.accept(self),
+            Stmt::IfStmt(n) => n.accept(self),
+            Stmt::PrintStmt(n) => n.accept(self),
+            Stmt::ReturnStmt(n) => n.accept(self),
+            Stmt::VarStmt(n) => n.accept(self),
+            Stmt::WhileStmt(n) => n.accept(self),
+        };
+        // Printing never fails; the visitors only return `Result` because
+        // the traits demand it.
+        result.unwrap_or_default()
+    }
+
+    pub fn print_expr(&self, expr: &Expr) -> String {
+        let result = match expr {
+            Expr::Assign(n) => n.accept(self),
+            Expr::Binary(n) => n.accept(self),
+            Expr::Call(n) => n.accept(self),
+            Expr::Get(n) => n.accept(self),
+            Expr::Grouping(n) => n.accept(self),
+            Expr::Interpolation(n) => n.accept(self),
+            Expr::Literal(n) => n.accept(self),
+            Expr::Logical(n) => n.accept(self),
+            Expr::Set(n) => n.accept(self),
+            Expr::Super(n) => n.accept(self),
+            Expr::This(n) => n.accept(self),
+            Expr::Unary(n) => n.accept(self),
+            Expr::Variable(n) => n.accept(self),
+        };
+        result.unwrap_or_default()
+    }
+
+    /// `(name part...)`, leaving out empty parts.
+    fn parenthesize(&self, name: &str, parts: &[String]) -> Result<String, LoxError> {
+        let mut out = format!("({name}");
+        for part in parts.iter().filter(|part| !part.is_empty()) {
+            out.push(' ');
+            out.push_str(part);
+        }
+        out.push(')');
+        Ok(out)
+    }
+
+    fn print_function(&self, function: &FunctionStmt) -> String {
+        let params: Vec<&str> = function.params.iter().map(|p| p.lexeme.as_str()).collect();
+        let mut parts = vec![
+            function.name.lexeme.clone(),
+            format!("({})", params.join(" ")),
+        ];
+        parts.extend(function.body.iter().map(|stmt| self.print_stmt(stmt)));
+        self.parenthesize("fun", &parts).unwrap_or_default()
+    }
+}
+
+impl ExprVisitor<String> for AstPrinter {
+    fn visit_assign_expr(&self, expr: &AssignExpr) -> Result<String, LoxError> {
+        self.parenthesize(
+            "=",
+            &[expr.name.lexeme.clone(), self.print_expr(&expr.value)],
+        )
+    }
+
+    fn visit_binary_expr(&self, expr: &BinaryExpr) -> Result<String, LoxError> {
+        self.parenthesize(
+            &expr.operator.lexeme,
+            &[self.print_expr(&expr.left), self.print_expr(&expr.right)],
+        )
+    }
+
+    fn visit_call_expr(&self, expr: &CallExpr) -> Result<String, LoxError> {
+        let mut parts = vec![self.print_expr(&expr.callee)];
+        parts.extend(expr.arguments.iter().map(|arg| self.print_expr(arg)));
+        self.parenthesize("call", &parts)
+    }
+
+    fn visit_get_expr(&self, expr: &GetExpr) -> Result<String, LoxError> {
+        self.parenthesize(
+            ".",
+            &[self.print_expr(&expr.object), expr.name.lexeme.clone()],
+        )
+    }
+
+    fn visit_grouping_expr(&self, expr: &GroupingExpr) -> Result<String, LoxError> {
+        self.parenthesize("group", &[self.print_expr(&expr.expression)])
+    }
+
+    fn visit_interpolation_expr(&self, expr: &InterpolationExpr) -> Result<String, LoxError> {
+        let parts: Vec<String> = expr
+            .parts
+            .iter()
+            .map(|part| self.print_expr(part))
+            .collect();
+        self.parenthesize("interpolate", &parts)
+    }
+
+    fn visit_literal_expr(&self, expr: &LiteralExpr) -> Result<String, LoxError> {
+        Ok(match &expr.value {
+            Object::Num(n) => n.to_string(),
+            // Quoted so a string can't be mistaken for a variable.
+            Object::Str(s) => format!("{s:?}"),
+            Object::Nil => String::from("nil"),
+            Object::Bool(b) => b.to_string(),
+            value => format!("{value:?}"),
+        })
+    }
+
+    fn visit_logical_expr(&self, expr: &LogicalExpr) -> Result<String, LoxError> {
+        self.parenthesize(
+            &expr.operator.lexeme,
+            &[self.print_expr(&expr.left), self.print_expr(&expr.right)],
+        )
+    }
+
+    fn visit_set_expr(&self, expr: &SetExpr) -> Result<String, LoxError> {
+        let target = self.parenthesize(
+            ".",
+            &[self.print_expr(&expr.object), expr.name.lexeme.clone()],
+        )?;
+        self.parenthesize("=", &[target, self.print_expr(&expr.value)])
+    }
+
+    fn visit_super_expr(&self, expr: &SuperExpr) -> Result<String, LoxError> {
+        self.parenthesize("super", std::slice::from_ref(&expr.method.lexeme))
+    }
+
+    fn visit_this_expr(&self, _expr: &ThisExpr) -> Result<String, LoxError> {
+        Ok(String::from("this"))
+    }
+
+    fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<String, LoxError> {
+        self.parenthesize(&expr.operator.lexeme, &[self.print_expr(&expr.right)])
+    }
+
+    fn visit_variable_expr(&self, expr: &VariableExpr) -> Result<String, LoxError> {
+        Ok(expr.name.lexeme.clone())
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_block_stmt(&self, stmt: &BlockStmt) -> Result<String, LoxError> {
+        let parts: Vec<String> = stmt.statements.iter().map(|s| self.print_stmt(s)).collect();
+        self.parenthesize("block", &parts)
+    }
+
+    fn visit_class_stmt(&self, stmt: &ClassStmt) -> Result<String, LoxError> {
+        let mut parts = vec![stmt.name.lexeme.clone()];
+        if let Some(superclass) = &stmt.superclass {
+            parts.push(format!("< {}", superclass.name.lexeme));
+        }
+        parts.extend(
+            stmt.methods
+                .iter()
+                .map(|method| self.print_function(method)),
+        );
+        self.parenthesize("class", &parts)
+    }
+
+    fn visit_expression_stmt(&self, stmt: &ExpressionStmt) -> Result<String, LoxError> {
+        self.parenthesize(";", &[self.print_expr(&stmt.expression)])
+    }
+
+    fn visit_function_stmt(&self, stmt: &FunctionStmt) -> Result<String, LoxError> {
+        Ok(self.print_function(stmt))
+    }
+
+    fn visit_if_stmt(&self, stmt: &IfStmt) -> Result<String, LoxError> {
+        let mut parts = vec![
+            self.print_expr(&stmt.condition),
+            self.print_stmt(&stmt.then_branch),
+        ];
+        if let Some(else_branch) = &stmt.else_branch {
+            parts.push(self.print_stmt(else_branch));
+        }
+        self.parenthesize("if", &parts)
+    }
+
+    fn visit_print_stmt(&self, stmt: &PrintStmt) -> Result<String, LoxError> {
+        self.parenthesize("print", &[self.print_expr(&stmt.expression)])
+    }
+
+    fn visit_return_stmt(&self, stmt: &ReturnStmt) -> Result<String, LoxError> {
+        let value = stmt.value.as_ref().map(|value| self.print_expr(value));
+        self.parenthesize("return", &[value.unwrap_or_default()])
+    }
+
+    fn visit_var_stmt(&self, stmt: &VarStmt) -> Result<String, LoxError> {
+        let initializer = stmt.initializer.as_ref().map(|init| self.print_expr(init));
+        self.parenthesize(
+            "var",
+            &[stmt.name.lexeme.clone(), initializer.unwrap_or_default()],
+        )
+    }
+
+    fn visit_while_stmt(&self, stmt: &WhileStmt) -> Result<String, LoxError> {
+        self.parenthesize(
+            "while",
+            &[
+                self.print_expr(&stmt.condition),
+                self.print_stmt(&stmt.body),
+            ],
+        )
+    }
+}
@@ -1,54 +1,328 @@
-use lox::lox::Lox;
+use lox::{diagnostic::Format, error::LoxError, lox::Lox};
+use rustyline::{error::ReadlineError, DefaultEditor};
 use std::{
     env::args,
     fs::File,
-    io::{self, BufReader, Read, Write},
-    process,
+    io::{self, BufReader, Read},
+    process, thread,
+    time::Instant,
 };
 
-fn main() {
-    let args: Vec<String> = args().collect();
+const USAGE: &str = "\
+Usage: lox [options] [run] <script>   run a script ('-' reads standard input)
+       lox [options] check <script>   report errors without running the script
+       lox [options] tokens <script>  print the tokens the script scans to
+       lox [options] ast [--json] <script>
+                                      print the syntax tree the script parses to
+       lox [options] exec <tree>      run a syntax tree saved by 'ast --json'
+       lox [options] -e <code>        run a line of code
+       lox [options] [repl]           start an interactive session
 
-    if args.len() > 3 {
-        println!("Usage: lox-ast [script]");
-    } else if args.len() == 2 {
-        run_file(&args[0]).expect("Could not run file");
-    } else {
-        run_prompt();
+Options:
+       --error-format pretty|plain|json";
+
+// Exit codes follow the BSD sysexits convention, as the reference
+// implementation does.
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
+
+/// Stack size of the thread scripts run on. The interpreter stops runaway
+/// recursion at its call limit, but a debug build needs more stack than the
+/// main thread's 8 MiB to get that deep.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// REPL history, kept in the user's home directory.
+const HISTORY_FILE: &str = ".lox_history";
+
+enum Command {
+    Run(String),
+    Check(String),
+    Tokens(String),
+    Ast(String),
+    AstJson(String),
+    Exec(String),
+    Eval(String),
+    Repl,
+}
+
+fn main() {
+    let lox = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(lox_main)
+        .expect("the interpreter thread could not be started");
+    if lox.join().is_err() {
+        process::exit(EX_SOFTWARE);
     }
 }
 
-fn run_file(path: &str) -> io::Result<()> {
-    let f = File::open(path)?;
-    let mut reader = BufReader::new(f);
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf);
+fn lox_main() {
+    let Some((command, format)) = parse_args(args().skip(1).collect()) else {
+        eprintln!("{USAGE}");
+        process::exit(EX_USAGE);
+    };
 
-    let source = String::from_utf8_lossy(&buf).to_string();
     let mut l = Lox::new();
+    l.format = format;
+    match command {
+        Command::Run(path) => run_file(&mut l, &path, false),
+        Command::Check(path) => run_file(&mut l, &path, true),
+        Command::Tokens(path) => dump_file(&mut l, &path, Lox::dump_tokens),
+        Command::Ast(path) => dump_file(&mut l, &path, Lox::dump_ast),
+        Command::AstJson(path) => dump_file(&mut l, &path, Lox::dump_ast_json),
+        Command::Exec(path) => exec_file(&mut l, &path),
+        Command::Eval(code) => run_source(&mut l, "<-e>", code, false),
+        Command::Repl => run_prompt(&mut l),
+    }
 
-    l.run(source);
     if l.had_error {
-        process::exit(65);
+        process::exit(EX_DATAERR);
+    }
+    if l.had_runtime_error {
+        process::exit(EX_SOFTWARE);
     }
-    Ok(())
 }
 
-fn run_prompt() {
-    let mut l = Lox::new();
+/// `None` if the arguments don't make sense.
+fn parse_args(args: Vec<String>) -> Option<(Command, Format)> {
+    let mut format = Format::default();
+    let mut words = vec![];
+    let mut code = None;
+    let mut json = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-e" {
+            code = Some(args.next()?);
+        } else if arg == "--json" {
+            json = true;
+        } else if let Some(value) = arg.strip_prefix("--error-format") {
+            let value = match value {
+                "" => args.next()?,
+                value => value.strip_prefix('=')?.to_string(),
+            };
+            format = value.parse().ok()?;
+        } else if arg.starts_with('-') && arg != "-" {
+            return None;
+        } else {
+            words.push(arg);
+        }
+    }
+
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let command = match (code, words.as_slice()) {
+        (Some(code), []) => Command::Eval(code),
+        (Some(_), _) => return None,
+        (None, []) | (None, ["repl"]) => Command::Repl,
+        (None, ["run", path]) => Command::Run(path.to_string()),
+        (None, [path]) if !matches!(*path, "run" | "check" | "tokens" | "ast" | "exec") => {
+            Command::Run(path.to_string())
+        }
+        (None, ["check", path]) => Command::Check(path.to_string()),
+        (None, ["tokens", path]) => Command::Tokens(path.to_string()),
+        (None, ["ast", path]) if json => Command::AstJson(path.to_string()),
+        (None, ["ast", path]) => Command::Ast(path.to_string()),
+        (None, ["exec", path]) => Command::Exec(path.to_string()),
+        _ => return None,
+    };
+    if json && !matches!(command, Command::AstJson(_)) {
+        return None;
+    }
+    Some((command, format))
+}
+
+fn run_file(l: &mut Lox, path: &str, check_only: bool) {
+    let (name, source) = read_file(l, path);
+    run_source(l, name, source, check_only);
+}
+
+/// A `Lox` method that turns a named script into text to print.
+type Dump = fn(&mut Lox, &str, String) -> Result<String, Vec<LoxError>>;
+
+/// Prints what `dump` makes of the script at `path`, or the errors it ran
+/// into.
+fn dump_file(l: &mut Lox, path: &str, dump: Dump) {
+    let (name, source) = read_file(l, path);
+    match dump(l, name, source) {
+        Ok(dump) => print!("{dump}"),
+        Err(errors) => report_all(l, &errors, name),
+    }
+}
+
+/// Runs the JSON syntax tree at `path`. There is no source text to quote,
+/// so errors are reported by line and column only.
+fn exec_file(l: &mut Lox, path: &str) {
+    let (name, json) = read_file(l, path);
+    if let Err(errors) = l.run_ast(&json) {
+        report_all(l, &errors, name);
+    }
+}
+
+fn report_all(l: &mut Lox, errors: &[LoxError], file: &str) {
+    for err in errors {
+        l.report(err, file);
+    }
+}
+
+/// Reads `path` like `read_source`, together with the name to report errors
+/// under. Exits if the file can't be read, or isn't UTF-8.
+fn read_file<'a>(l: &mut Lox, path: &'a str) -> (&'a str, String) {
+    match read_source(path) {
+        Ok(source) if path == "-" => ("<stdin>", source),
+        Ok(source) => (path, source),
+        Err(err) => {
+            let code = if err.kind() == io::ErrorKind::InvalidData {
+                EX_DATAERR
+            } else {
+                EX_NOINPUT
+            };
+            l.report(&LoxError::Io(err), path);
+            process::exit(code);
+        }
+    }
+}
+
+/// Reads the script at `path`, or standard input for "-". Input that isn't
+/// valid UTF-8 is an `InvalidData` error wrapping the `Utf8Error`, which
+/// names the first bad byte, rather than being patched up and run.
+fn read_source(path: &str) -> io::Result<String> {
+    let mut buf = Vec::new();
+    if path == "-" {
+        io::stdin().read_to_end(&mut buf)?;
+    } else {
+        BufReader::new(File::open(path)?).read_to_end(&mut buf)?;
+    }
+    String::from_utf8(buf)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.utf8_error()))
+}
+
+fn run_source(l: &mut Lox, name: &str, source: String, check_only: bool) {
+    let result = if check_only {
+        l.check(name, source)
+    } else {
+        l.run(name, source)
+    };
+    if let Err(errors) = result {
+        report_all(l, &errors, name);
+    }
+}
+
+fn run_prompt(l: &mut Lox) {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("<stdin>: {err}");
+            l.had_error = true;
+            return;
+        }
+    };
+    let history = home::home_dir().map(|home| home.join(HISTORY_FILE));
+    if let Some(history) = &history {
+        // There is no history yet the first time the REPL starts.
+        let _ = editor.load_history(history);
+    }
+
+    let mut buf = String::new();
     loop {
-        print!("> ");
-        io::stdout().flush();
-        let mut buf = String::new();
-        let _ = io::stdin().read_line(&mut buf);
-        if buf.starts_with("exit\n") {
-            break;
+        let prompt = if buf.is_empty() { "> " } else { "... " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                buf.push_str(&line);
+                buf.push('\n');
+            }
+            // Ctrl-C throws away the statement being typed.
+            Err(ReadlineError::Interrupted) => {
+                buf.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("<stdin>: {err}");
+                l.had_error = true;
+                break;
+            }
         }
-        if buf.is_empty() {
+        if buf.trim() == "exit" {
             break;
+        }
+        // No Lox statement starts with ':', so those lines are always
+        // meta-commands and never need continuing.
+        let is_command = buf.trim_start().starts_with(':');
+        if buf.trim().is_empty() {
+            // Drop the blank line so the prompt and line numbers start over.
+            buf.clear();
+            continue;
+        }
+        if !is_command && l.is_incomplete(&buf) {
+            continue;
+        }
+
+        let source = std::mem::take(&mut buf);
+        let _ = editor.add_history_entry(source.trim_end());
+        if is_command {
+            run_command(l, source.trim());
         } else {
-            l.run(buf).unwrap();
-            l.had_error = false;
+            run_repl_source(l, source);
+        }
+        // A mistake in one line shouldn't end the session or its exit code.
+        l.had_error = false;
+        l.had_runtime_error = false;
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+}
+
+const REPL_HELP: &str = "\
+:env            list global variables and their values
+:tokens <code>  show the tokens <code> scans to
+:ast <code>     show the syntax tree <code> parses to
+:load <file>    run a script in this session
+:reset          forget everything defined so far
+:time <code>    run <code> and show how long it took
+:help           show this list";
+
+/// Runs REPL input, printing the value if it's a bare expression.
+fn run_repl_source(l: &mut Lox, source: String) {
+    match l.run_repl("<stdin>", source) {
+        Ok(Some(value)) => println!("{value}"),
+        Ok(None) => {}
+        Err(errors) => report_all(l, &errors, "<stdin>"),
+    }
+}
+
+fn run_command(l: &mut Lox, line: &str) {
+    let (command, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let arg = arg.trim();
+    match command {
+        ":env" => {
+            for (name, value) in l.globals() {
+                println!("{name} = {value}");
+            }
+        }
+        ":tokens" | ":ast" => {
+            let dump = if command == ":tokens" {
+                l.dump_tokens("<stdin>", arg.to_string())
+            } else {
+                l.dump_ast("<stdin>", arg.to_string())
+            };
+            match dump {
+                Ok(dump) => print!("{dump}"),
+                Err(errors) => report_all(l, &errors, "<stdin>"),
+            }
+        }
+        ":load" if !arg.is_empty() => match read_source(arg) {
+            Ok(source) => run_source(l, arg, source, false),
+            Err(err) => l.report(&LoxError::Io(err), arg),
+        },
+        ":reset" => l.reset(),
+        ":time" => {
+            let start = Instant::now();
+            run_repl_source(l, arg.to_string());
+            println!("took {:?}", start.elapsed());
         }
+        ":help" => println!("{REPL_HELP}"),
+        _ => eprintln!("unknown command '{line}', see :help"),
     }
 }
@@ -0,0 +1,53 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{error::LoxError, interpreter::Interpreter, token::Object};
+
+pub trait LoxCallable: fmt::Display {
+    /// Name shown for this callable in runtime stack traces.
+    fn name(&self) -> &str;
+    fn arity(&self) -> usize;
+    fn call(&self, interpreter: &Interpreter, arguments: Vec<Object>) -> Result<Object, LoxError>;
+}
+
+impl fmt::Debug for dyn LoxCallable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// A function implemented in Rust and exposed to Lox scripts as a global.
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub func: fn(&[Object]) -> Result<Object, LoxError>,
+}
+
+impl LoxCallable for NativeFunction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, LoxError> {
+        (self.func)(&arguments)
+    }
+}
+
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+pub fn clock(_: &[Object]) -> Result<Object, LoxError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(Object::Num(now.as_secs_f64()))
+}
@@ -0,0 +1,483 @@
+use std::{fmt::Write, io, str::FromStr, str::Utf8Error};
+
+use serde::{Deserialize, Serialize};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{
+    error::LoxError,
+    message::{Lang, Message},
+    span::Span,
+};
+
+/// Stable identifier for each kind of error Lox reports. The codes never
+/// change meaning once assigned, so tools can match on them instead of on
+/// message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnterminatedString,
+    UnexpectedCharacter,
+    ExpectedToken,
+    ExpectedExpression,
+    InvalidAssignmentTarget,
+    TooManyArguments,
+    TooManyParameters,
+    InheritFromSelf,
+    ReadInOwnInitializer,
+    AlreadyDeclared,
+    TopLevelReturn,
+    ReturnFromInitializer,
+    ThisOutsideClass,
+    SuperOutsideClass,
+    SuperWithoutSuperclass,
+    OperandNotNumber,
+    InvalidPlusOperands,
+    UndefinedVariable,
+    UndefinedProperty,
+    NotAnInstance,
+    NotCallable,
+    ArityMismatch,
+    SuperclassNotClass,
+    UnknownOperator,
+    InvalidEscape,
+    MalformedNumber,
+    UnterminatedBlockComment,
+    StackOverflow,
+    Io,
+    InvalidSyntaxTree,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::UnterminatedString => "L0001",
+            ErrorCode::UnexpectedCharacter => "L0002",
+            ErrorCode::ExpectedToken => "L0003",
+            ErrorCode::ExpectedExpression => "L0004",
+            ErrorCode::InvalidAssignmentTarget => "L0005",
+            ErrorCode::TooManyArguments => "L0006",
+            ErrorCode::TooManyParameters => "L0007",
+            ErrorCode::InheritFromSelf => "L0008",
+            ErrorCode::ReadInOwnInitializer => "L0009",
+            ErrorCode::AlreadyDeclared => "L0010",
+            ErrorCode::TopLevelReturn => "L0011",
+            ErrorCode::ReturnFromInitializer => "L0012",
+            ErrorCode::ThisOutsideClass => "L0013",
+            ErrorCode::SuperOutsideClass => "L0014",
+            ErrorCode::SuperWithoutSuperclass => "L0015",
+            ErrorCode::OperandNotNumber => "L0016",
+            ErrorCode::InvalidPlusOperands => "L0017",
+            ErrorCode::UndefinedVariable => "L0018",
+            ErrorCode::UndefinedProperty => "L0019",
+            ErrorCode::NotAnInstance => "L0020",
+            ErrorCode::NotCallable => "L0021",
+            ErrorCode::ArityMismatch => "L0022",
+            ErrorCode::SuperclassNotClass => "L0023",
+            ErrorCode::UnknownOperator => "L0024",
+            ErrorCode::InvalidEscape => "L0025",
+            ErrorCode::MalformedNumber => "L0026",
+            ErrorCode::UnterminatedBlockComment => "L0027",
+            ErrorCode::StackOverflow => "L0028",
+            ErrorCode::Io => "L0029",
+            ErrorCode::InvalidSyntaxTree => "L0030",
+        }
+    }
+}
+
+/// How diagnostics are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// The offending source lines with the spans underlined.
+    #[default]
+    Pretty,
+    /// One `file:line:column: error[code]: message` line per location.
+    Plain,
+    /// One `DiagnosticRecord` JSON object per line, for CI and editors.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(Format::Pretty),
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown diagnostic format '{s}'")),
+        }
+    }
+}
+
+/// A secondary location that helps explain an error, such as where an
+/// unclosed parenthesis was opened.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub msg: String,
+}
+
+/// Everything needed to show one error to a user, independent of which
+/// stage of the pipeline produced it.
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub msg: String,
+    pub span: Span,
+    /// Where the span starts, for when the source text isn't available.
+    pub line: usize,
+    pub column: usize,
+    pub labels: Vec<Label>,
+    /// Runtime call stack, innermost first.
+    pub trace: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Describes `err` in `lang`. I/O errors have no position, so their line
+    /// and column are 0.
+    pub fn from_error(err: &LoxError, lang: Lang) -> Diagnostic {
+        match err {
+            LoxError::Scan(err) | LoxError::Parse(err) | LoxError::Resolve(err) => Diagnostic {
+                code: err.msg.code(),
+                msg: err.msg.text(lang),
+                span: err.span,
+                line: err.line,
+                column: err.column,
+                labels: err
+                    .labels
+                    .iter()
+                    .map(|(span, note)| Label {
+                        span: *span,
+                        msg: note.text(lang).to_string(),
+                    })
+                    .collect(),
+                trace: vec![],
+            },
+            LoxError::Runtime(err) => Diagnostic {
+                code: err.msg.code(),
+                msg: err.msg.text(lang),
+                span: err.span,
+                line: err.line,
+                column: err.column,
+                labels: vec![],
+                trace: err.stack_trace(lang),
+            },
+            LoxError::Io(err) => Diagnostic {
+                code: ErrorCode::Io,
+                msg: io_message(err).text(lang),
+                span: Span::default(),
+                line: 0,
+                column: 0,
+                labels: vec![],
+                trace: vec![],
+            },
+            LoxError::Load(err) => Diagnostic {
+                code: ErrorCode::InvalidSyntaxTree,
+                msg: load_message(err).text(lang),
+                span: Span::default(),
+                line: err.line(),
+                column: err.column(),
+                labels: vec![],
+                trace: vec![],
+            },
+        }
+    }
+
+    /// Renders the diagnostic for `source`, the contents of `file`. Without
+    /// the source, as for a syntax tree loaded from JSON, only the line and
+    /// column are shown and labels are left out.
+    pub fn render(&self, file: &str, source: Option<&str>, format: Format) -> String {
+        match (format, source) {
+            (Format::Pretty, Some(source)) => self.render_pretty(file, source),
+            (Format::Pretty, None) => self.render_pretty_without_source(file),
+            (Format::Plain, _) => self.render_plain(file, source),
+            (Format::Json, _) => self.render_json(file, source),
+        }
+    }
+
+    /// Line and column of the span's start and end in `source`, or the
+    /// diagnostic's own position for both without it.
+    fn position(&self, span: Span, source: Option<&str>) -> (usize, usize, usize, usize) {
+        match source {
+            Some(source) => {
+                let (line, column) = span.line_column(source);
+                let (end_line, end_column) = span.end_line_column(source);
+                (line, column, end_line, end_column)
+            }
+            None => (self.line, self.column, self.line, self.column),
+        }
+    }
+
+    fn render_pretty_without_source(&self, file: &str) -> String {
+        let mut out = format!("error[{}]: {}\n", self.code.as_str(), self.msg);
+        let _ = writeln!(out, " --> {}", location(file, self.line, self.column));
+        if !self.trace.is_empty() {
+            let _ = writeln!(out, "  = stack trace:");
+            for frame in &self.trace {
+                let _ = writeln!(out, "        {frame}");
+            }
+        }
+        out
+    }
+
+    fn render_pretty(&self, file: &str, source: &str) -> String {
+        let (line, column) = self.span.line_column(source);
+        let mut out = format!("error[{}]: {}\n", self.code.as_str(), self.msg);
+
+        // Every underline to draw, primary first, ordered by where it starts.
+        let mut marks = vec![(self.span, '^', "")];
+        marks.extend(self.labels.iter().map(|l| (l.span, '-', l.msg.as_str())));
+        marks.sort_by_key(|(span, _, _)| span.start);
+
+        let last_line = marks
+            .iter()
+            .map(|(span, _, _)| span.line_column(source).0)
+            .max()
+            .unwrap_or(line);
+        let gutter = " ".repeat(last_line.to_string().len());
+
+        let _ = writeln!(out, "{gutter}--> {file}:{line}:{column}");
+        let _ = writeln!(out, "{gutter} |");
+        let mut previous_line = None;
+        for (span, marker, msg) in marks {
+            let line = span.line_column(source).0;
+            if previous_line != Some(line) {
+                if previous_line.is_some_and(|previous| line > previous + 1) {
+                    let _ = writeln!(out, "{gutter}...");
+                }
+                let text = source.lines().nth(line - 1).unwrap_or("");
+                let row = format!("{line:>width$} | {text}", width = gutter.len());
+                let _ = writeln!(out, "{}", row.trim_end());
+                previous_line = Some(line);
+            }
+            let padding = blank(line_before(span, source));
+            let underline = marker.to_string().repeat(underline_width(span, source));
+            let line = format!("{gutter} | {padding}{underline} {msg}");
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+        let _ = writeln!(out, "{gutter} |");
+
+        if !self.trace.is_empty() {
+            let _ = writeln!(out, "{gutter} = stack trace:");
+            for frame in &self.trace {
+                let _ = writeln!(out, "{gutter}       {frame}");
+            }
+        }
+        out
+    }
+
+    fn render_plain(&self, file: &str, source: Option<&str>) -> String {
+        let (line, column, _, _) = self.position(self.span, source);
+        let mut out = format!(
+            "{}: error[{}]: {}\n",
+            location(file, line, column),
+            self.code.as_str(),
+            self.msg
+        );
+        for label in self.labels.iter().filter(|_| source.is_some()) {
+            let (line, column, _, _) = self.position(label.span, source);
+            let _ = writeln!(out, "{file}:{line}:{column}: note: {}", label.msg);
+        }
+        for frame in &self.trace {
+            let _ = writeln!(out, "    {frame}");
+        }
+        out
+    }
+
+    fn render_json(&self, file: &str, source: Option<&str>) -> String {
+        let mut line = serde_json::to_string(&self.to_record(file, source))
+            .expect("a diagnostic record always serializes");
+        line.push('\n');
+        line
+    }
+
+    /// The diagnostic as it appears in `Format::Json` output.
+    pub fn to_record(&self, file: &str, source: Option<&str>) -> DiagnosticRecord {
+        let (line, column, end_line, end_column) = self.position(self.span, source);
+        DiagnosticRecord {
+            file: file.to_string(),
+            line,
+            column,
+            end_line,
+            end_column,
+            severity: Severity::Error,
+            code: self.code.as_str().to_string(),
+            message: self.msg.clone(),
+            labels: self
+                .labels
+                .iter()
+                .filter(|_| source.is_some())
+                .map(|label| {
+                    let (line, column, end_line, end_column) = self.position(label.span, source);
+                    LabelRecord {
+                        line,
+                        column,
+                        end_line,
+                        end_column,
+                        message: label.msg.clone(),
+                    }
+                })
+                .collect(),
+            trace: self.trace.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+}
+
+/// One line of `Format::Json` output. Lines and columns are 1-based, columns
+/// count characters, and the end position is exclusive. See the README for
+/// the full schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiagnosticRecord {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub severity: Severity,
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<LabelRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trace: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelRecord {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub message: String,
+}
+
+/// The message for an I/O error. The common failures are spelled out so they
+/// can be translated; others fall back to the operating system's text.
+fn io_message(err: &io::Error) -> Message {
+    if let Some(utf8) = err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<Utf8Error>())
+    {
+        return Message::InvalidUtf8(utf8.valid_up_to());
+    }
+    match err.kind() {
+        io::ErrorKind::NotFound => Message::FileNotFound,
+        io::ErrorKind::PermissionDenied => Message::PermissionDenied,
+        _ => Message::Io(err.to_string()),
+    }
+}
+
+/// The message for a syntax tree that failed to load. The position is left
+/// out of serde's text since the diagnostic shows it already.
+fn load_message(err: &serde_json::Error) -> Message {
+    let text = err.to_string();
+    let position = format!(" at line {} column {}", err.line(), err.column());
+    let detail = text.strip_suffix(&position).unwrap_or(&text);
+    Message::InvalidSyntaxTree(detail.to_string())
+}
+
+/// `file:line:column`, or just `file` when the position is unknown.
+fn location(file: &str, line: usize, column: usize) -> String {
+    if line == 0 {
+        file.to_string()
+    } else {
+        format!("{file}:{line}:{column}")
+    }
+}
+
+/// Number of terminal columns to underline: the width of the part of `span`
+/// on its first line, and at least one so an empty span at end of input
+/// still shows a caret. Columns rather than characters, so that wide
+/// characters such as `变量` are underlined in full.
+fn underline_width(span: Span, source: &str) -> usize {
+    let text = span.text(source);
+    let first_line = text.split('\n').next().unwrap_or("");
+    first_line.width().max(1)
+}
+
+/// The text between the start of the line `span` starts on and the span.
+fn line_before(span: Span, source: &str) -> &str {
+    let before = source.get(..span.start).unwrap_or(source);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    &before[line_start..]
+}
+
+/// Blank space as wide on screen as `text`. Tabs are kept as they are, so the
+/// underline lines up however the terminal expands them.
+fn blank(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\t' => String::from('\t'),
+            c => " ".repeat(c.width().unwrap_or(0)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Message, Note};
+    use std::io;
+
+    fn record(err: &LoxError, source: Option<&str>) -> DiagnosticRecord {
+        let diagnostic = Diagnostic::from_error(err, Lang::En);
+        let json = diagnostic.render("a.lox", source, Format::Json);
+        assert!(json.ends_with('\n') && json.trim_end().lines().count() == 1);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn json_output_parses_as_a_record() {
+        let source = "print (1 + 2;\n";
+        let err = LoxError::new_scan(
+            Message::ExpectRightParenAfterExpression,
+            1,
+            13,
+            Span::new(12, 13),
+        )
+        .with_label(Span::new(6, 7), Note::OpeningParenthesis);
+
+        assert_eq!(
+            record(&err, Some(source)),
+            DiagnosticRecord {
+                file: "a.lox".into(),
+                line: 1,
+                column: 13,
+                end_line: 1,
+                end_column: 14,
+                severity: Severity::Error,
+                code: "L0003".into(),
+                message: "Expect ')' after expression.".into(),
+                labels: vec![LabelRecord {
+                    line: 1,
+                    column: 7,
+                    end_line: 1,
+                    end_column: 8,
+                    message: "opening parenthesis is here".into(),
+                }],
+                trace: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn json_output_without_source_uses_the_error_position() {
+        let err = LoxError::new_scan(Message::UnexpectedCharacter, 3, 5, Span::new(40, 41));
+        let record = record(&err, None);
+        assert_eq!((record.line, record.column), (3, 5));
+        assert_eq!((record.end_line, record.end_column), (3, 5));
+        assert!(record.labels.is_empty());
+    }
+
+    #[test]
+    fn io_errors_are_records_too() {
+        let err = LoxError::Io(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        let record = record(&err, None);
+        assert_eq!(record.code, "L0029");
+        assert_eq!((record.line, record.column), (0, 0));
+    }
+}
@@ -1,13 +1,15 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     error::LoxError,
     hashmap,
+    message::Message,
     token::{Object, Token},
 };
 
 pub struct Environment {
     values: HashMap<String, Object>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
@@ -15,17 +17,75 @@ impl Environment {
         self.values.insert(name, value);
     }
     pub fn get(&self, name: &Token) -> Result<Object, LoxError> {
-        if self.values.contains_key(&name.lexeme) {
-            Ok(self.values.get(&name.lexeme).unwrap().clone())
+        if let Some(value) = self.values.get(&name.lexeme) {
+            Ok(value.clone())
+        } else if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow().get(name)
         } else {
-            Err(LoxError::new_runtime(format!(
-                "Undefined variable '{}'.",
-                name.lexeme
-            )))
+            Err(LoxError::new_runtime(
+                name,
+                Message::UndefinedVariable(name.lexeme.clone()),
+            ))
+        }
+    }
+
+    /// The variables defined directly in this scope, in no particular order.
+    pub fn values(&self) -> impl Iterator<Item = (&String, &Object)> {
+        self.values.iter()
+    }
+
+    pub fn get_at(&self, distance: usize, name: &str) -> Option<Object> {
+        if distance == 0 {
+            self.values.get(name).cloned()
+        } else {
+            self.enclosing.as_ref()?.borrow().get_at(distance - 1, name)
+        }
+    }
+
+    pub fn assign_at(
+        &mut self,
+        distance: usize,
+        name: &Token,
+        value: Object,
+    ) -> Result<(), LoxError> {
+        if distance == 0 {
+            self.values.insert(name.lexeme.clone(), value);
+            Ok(())
+        } else if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow_mut().assign_at(distance - 1, name, value)
+        } else {
+            Err(LoxError::new_runtime(
+                name,
+                Message::UndefinedVariable(name.lexeme.clone()),
+            ))
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Object) -> Result<(), LoxError> {
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
+            Ok(())
+        } else if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow_mut().assign(name, value)
+        } else {
+            Err(LoxError::new_runtime(
+                name,
+                Message::UndefinedVariable(name.lexeme.clone()),
+            ))
         }
     }
 
     pub fn new() -> Self {
-        Self { values: hashmap!() }
+        Self {
+            values: hashmap!(),
+            enclosing: None,
+        }
+    }
+
+    pub fn new_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: hashmap!(),
+            enclosing: Some(enclosing),
+        }
     }
 }
@@ -1,25 +1,216 @@
+use std::{error::Error, fmt, io};
+
+use crate::{
+    message::{Lang, Message, Note},
+    span::Span,
+    token::Token,
+};
+
+/// Everything that can go wrong running Lox code, split by the stage that
+/// found the problem.
 #[derive(Debug)]
 pub enum LoxError {
-    Compile(CompileError),
-    Runtime(RuntimeError),
+    /// The scanner couldn't turn the source into tokens.
+    Scan(CompileError),
+    /// The tokens don't form a valid program.
+    Parse(CompileError),
+    /// The program is well-formed but misuses scopes, as the resolver found.
+    Resolve(CompileError),
+    /// Boxed so the `Result`s threaded through every visitor stay small.
+    Runtime(Box<RuntimeError>),
+    /// The source couldn't be read.
+    Io(io::Error),
+    /// A serialized syntax tree couldn't be loaded.
+    Load(serde_json::Error),
 }
 
 impl LoxError {
-    pub fn new_compile(msg: String, line: usize) -> LoxError {
-        LoxError::Compile(CompileError { msg, line })
+    pub fn new_scan(msg: Message, line: usize, column: usize, span: Span) -> LoxError {
+        LoxError::Scan(CompileError::new(msg, line, column, span))
+    }
+    pub(crate) fn new_parse(token: &Token, msg: Message) -> LoxError {
+        LoxError::Parse(CompileError::at(token, msg))
     }
-    pub fn new_runtime(msg: String) -> LoxError {
-        LoxError::Runtime(RuntimeError { msg: msg })
+    pub(crate) fn new_resolve(token: &Token, msg: Message) -> LoxError {
+        LoxError::Resolve(CompileError::at(token, msg))
+    }
+    pub(crate) fn new_runtime(token: &Token, msg: Message) -> LoxError {
+        LoxError::Runtime(Box::new(RuntimeError {
+            msg,
+            line: token.line,
+            column: token.column,
+            span: token.span,
+            trace: vec![],
+        }))
+    }
+
+    /// The part of the source the error points at, if it points anywhere.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            LoxError::Scan(err) | LoxError::Parse(err) | LoxError::Resolve(err) => Some(err.span),
+            LoxError::Runtime(err) => Some(err.span),
+            LoxError::Io(_) | LoxError::Load(_) => None,
+        }
+    }
+
+    /// Attaches a secondary label to a compile error. Other errors are
+    /// returned unchanged.
+    pub fn with_label(mut self, span: Span, note: Note) -> LoxError {
+        if let LoxError::Scan(err) | LoxError::Parse(err) | LoxError::Resolve(err) = &mut self {
+            err.labels.push((span, note));
+        }
+        self
+    }
+}
+
+/// Always English, for hosts that log errors themselves. `Lox::report`
+/// shows messages in `Lox::lang` instead.
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoxError::Scan(err) | LoxError::Parse(err) | LoxError::Resolve(err) => err.fmt(f),
+            LoxError::Runtime(err) => err.fmt(f),
+            LoxError::Io(err) => write!(f, "I/O error: {err}"),
+            LoxError::Load(err) => write!(f, "invalid syntax tree: {err}"),
+        }
     }
 }
 
+/// Scan, parse, resolve and runtime errors display their inner error
+/// directly, so only the wrapped I/O and JSON errors count as a source.
+impl Error for LoxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoxError::Scan(_) | LoxError::Parse(_) | LoxError::Resolve(_) => None,
+            LoxError::Runtime(_) => None,
+            LoxError::Io(err) => Some(err),
+            LoxError::Load(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for LoxError {
+    fn from(err: io::Error) -> Self {
+        LoxError::Io(err)
+    }
+}
+
+/// An error found before the program started running.
 #[derive(Debug)]
 pub struct CompileError {
-    pub msg: String,
+    pub msg: Message,
     pub line: usize,
+    pub column: usize,
+    pub span: Span,
+    pub labels: Vec<(Span, Note)>,
+}
+
+impl CompileError {
+    fn new(msg: Message, line: usize, column: usize, span: Span) -> Self {
+        Self {
+            msg,
+            line,
+            column,
+            span,
+            labels: vec![],
+        }
+    }
+
+    fn at(token: &Token, msg: Message) -> Self {
+        Self::new(msg, token.line, token.column, token.span)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] Error: {}",
+            self.line,
+            self.column,
+            self.msg.text(Lang::En)
+        )
+    }
 }
 
+impl Error for CompileError {}
+
 #[derive(Debug)]
 pub struct RuntimeError {
-    pub msg: String,
+    pub msg: Message,
+    /// Position of the operator or identifier being evaluated when the
+    /// error occurred.
+    pub line: usize,
+    pub column: usize,
+    pub span: Span,
+    /// Calls the error unwound through, innermost first.
+    pub trace: Vec<StackFrame>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] Runtime error: {}",
+            self.line,
+            self.column,
+            self.msg.text(Lang::En)
+        )
+    }
+}
+
+impl Error for RuntimeError {}
+
+#[derive(Debug)]
+pub struct StackFrame {
+    pub function: String,
+    /// Line of the call expression that invoked `function`.
+    pub line: usize,
+}
+
+impl RuntimeError {
+    /// Renders the trace as "[line N] in name" entries, innermost first, each
+    /// naming the line that was executing inside that function.
+    pub fn stack_trace(&self, lang: Lang) -> Vec<String> {
+        let mut lines = vec![];
+        let mut line = self.line;
+        for frame in &self.trace {
+            lines.push(match lang {
+                Lang::En => format!("[line {line}] in {}()", frame.function),
+                Lang::ZhCn => format!("[第 {line} 行] 位于 {}()", frame.function),
+            });
+            line = frame.line;
+        }
+        lines.push(match lang {
+            Lang::En => format!("[line {line}] in script"),
+            Lang::ZhCn => format!("[第 {line} 行] 位于脚本"),
+        });
+        collapse_repeats(lines, lang)
+    }
+}
+
+/// Identical entries shown in a row before the rest are summed up.
+const MAX_REPEATS: usize = 3;
+
+/// Shortens each run of identical trace entries, so runaway recursion doesn't
+/// print one line per call.
+fn collapse_repeats(lines: Vec<String>, lang: Lang) -> Vec<String> {
+    let mut collapsed = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        let run = lines[i..]
+            .iter()
+            .take_while(|line| **line == lines[i])
+            .count();
+        collapsed.extend_from_slice(&lines[i..i + run.min(MAX_REPEATS)]);
+        if run > MAX_REPEATS {
+            let more = run - MAX_REPEATS;
+            collapsed.push(match lang {
+                Lang::En => format!("[previous line repeated {more} more times]"),
+                Lang::ZhCn => format!("[上一行又重复了 {more} 次]"),
+            });
+        }
+        i += run;
+    }
+    collapsed
 }
@@ -0,0 +1,81 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    ast::FunctionStmt,
+    callable::LoxCallable,
+    environment::Environment,
+    error::LoxError,
+    instance::LoxInstance,
+    interpreter::{Interpreter, Unwind},
+    token::Object,
+};
+
+pub struct LoxFunction {
+    declaration: FunctionStmt,
+    closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn new(
+        declaration: FunctionStmt,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// Returns a copy of this method whose closure binds `this` to `instance`.
+    pub fn bind(&self, instance: Rc<LoxInstance>) -> LoxFunction {
+        let mut env = Environment::new_enclosing(self.closure.clone());
+        env.define(String::from("this"), Object::Instance(instance));
+        LoxFunction::new(
+            self.declaration.clone(),
+            Rc::new(RefCell::new(env)),
+            self.is_initializer,
+        )
+    }
+
+    fn this(&self) -> Object {
+        self.closure
+            .borrow()
+            .get_at(0, "this")
+            .unwrap_or(Object::Nil)
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(&self, interpreter: &Interpreter, arguments: Vec<Object>) -> Result<Object, LoxError> {
+        let mut env = Environment::new_enclosing(self.closure.clone());
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            env.define(param.lexeme.clone(), argument);
+        }
+
+        // An initializer always hands back the instance, even on a bare `return;`.
+        match interpreter.execute_block(&self.declaration.body, env) {
+            Ok(()) if self.is_initializer => Ok(self.this()),
+            Ok(()) => Ok(Object::Nil),
+            Err(Unwind::Return(_)) if self.is_initializer => Ok(self.this()),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(err)) => Err(err),
+        }
+    }
+}
+
+impl fmt::Display for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
+    }
+}
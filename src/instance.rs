@@ -0,0 +1,52 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use crate::{
+    class::LoxClass,
+    error::LoxError,
+    message::Message,
+    token::{Object, Token},
+};
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: RefCell<HashMap<String, Object>>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Looks up a field first and falls back to a method bound to `instance`.
+    pub fn get(instance: &Rc<LoxInstance>, name: &Token) -> Result<Object, LoxError> {
+        if let Some(value) = instance.fields.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        if let Some(method) = instance.class.find_method(&name.lexeme) {
+            return Ok(Object::Callable(Rc::new(method.bind(instance.clone()))));
+        }
+        Err(LoxError::new_runtime(
+            name,
+            Message::UndefinedProperty(name.lexeme.clone()),
+        ))
+    }
+
+    pub fn set(&self, name: &Token, value: Object) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+}
+
+impl fmt::Display for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
@@ -1,131 +1,375 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    io::{self, Write},
+    rc::Rc,
+};
+
 use crate::{
     ast::*,
+    callable::{self, LoxCallable, NativeFunction},
+    class::LoxClass,
     environment::Environment,
-    error::LoxError,
-    token::{Object, TokenType},
+    error::{LoxError, StackFrame},
+    function::LoxFunction,
+    instance::LoxInstance,
+    message::Message,
+    token::{Object, Token, TokenType},
 };
 
+/// Why executing statements stopped before the end. A `return` unwinds like
+/// an error does, but only as far as the call it returns from, so it never
+/// escapes the interpreter.
+pub enum Unwind {
+    Error(LoxError),
+    Return(Object),
+}
+
+impl From<LoxError> for Unwind {
+    fn from(err: LoxError) -> Self {
+        Unwind::Error(err)
+    }
+}
+
+/// Deepest nesting of calls allowed before a script is stopped with a
+/// "Stack overflow." error. Each Lox call takes several Rust frames, so this
+/// keeps well inside the main thread's stack even in debug builds.
+const MAX_CALL_DEPTH: usize = 1000;
+
 pub struct Interpreter {
-    env: Environment,
+    globals: Rc<RefCell<Environment>>,
+    env: RefCell<Rc<RefCell<Environment>>>,
+    /// Number of calls currently running.
+    call_depth: Cell<usize>,
 }
 
 impl ExprVisitor<Object> for Interpreter {
+    fn visit_assign_expr(&self, expr: &AssignExpr) -> Result<Object, LoxError> {
+        let value = self.evaluate(&expr.value)?;
+        match expr.depth.get() {
+            Some(distance) => {
+                self.env
+                    .borrow()
+                    .borrow_mut()
+                    .assign_at(distance, &expr.name, value.clone())?
+            }
+            None => self
+                .globals
+                .borrow_mut()
+                .assign(&expr.name, value.clone())?,
+        }
+        Ok(value)
+    }
+
     fn visit_binary_expr(&self, expr: &BinaryExpr) -> Result<Object, LoxError> {
-        let left = self.evaluate(&expr.left).unwrap().into();
-        let right = self.evaluate(&expr.right).unwrap();
-
-        if let Object::Num(left_num) = left {
-            if let Object::Num(right_num) = right {
-                match expr.operator.tty {
-                    TokenType::PLUS => return Ok(Object::Num(left_num + right_num)),
-                    TokenType::MINUS => return Ok(Object::Num(left_num - right_num)),
-                    TokenType::SLASH => return Ok(Object::Num(left_num / right_num)),
-                    TokenType::STAR => return Ok(Object::Num(left_num * right_num)),
-                    TokenType::GREATER => {
-                        return Ok(if left_num > right_num {
-                            Object::True
-                        } else {
-                            Object::False
-                        })
-                    }
-                    TokenType::GREATER_EQUAL => {
-                        return Ok(if left_num >= right_num {
-                            Object::True
-                        } else {
-                            Object::False
-                        })
-                    }
-                    TokenType::LESS => {
-                        return Ok(if left_num < right_num {
-                            Object::True
-                        } else {
-                            Object::False
-                        })
-                    }
-                    TokenType::LESS_EQUAL => {
-                        return Ok(if left_num <= right_num {
-                            Object::True
-                        } else {
-                            Object::False
-                        })
-                    }
-                    TokenType::BANG_EQUAL => {
-                        return Ok(if left_num != right_num {
-                            Object::True
-                        } else {
-                            Object::False
-                        })
-                    }
-                    TokenType::EQUAL_EQUAL => {
-                        return Ok(if left_num == right_num {
-                            Object::True
-                        } else {
-                            Object::False
-                        })
-                    }
-                    _ => {}
-                }
+        let left = self.evaluate(&expr.left)?;
+        let right = self.evaluate(&expr.right)?;
+        let operator = &expr.operator;
+
+        match operator.tty {
+            TokenType::PLUS => match (left, right) {
+                (Object::Num(l), Object::Num(r)) => Ok(Object::Num(l + r)),
+                (Object::Str(l), Object::Str(r)) => Ok(Object::Str(l + &r)),
+                _ => Err(LoxError::new_runtime(
+                    operator,
+                    Message::OperandsMustBeNumbersOrStrings,
+                )),
+            },
+            TokenType::MINUS => {
+                let (l, r) = self.number_operands(operator, &left, &right)?;
+                Ok(Object::Num(l - r))
+            }
+            TokenType::SLASH => {
+                let (l, r) = self.number_operands(operator, &left, &right)?;
+                Ok(Object::Num(l / r))
+            }
+            TokenType::STAR => {
+                let (l, r) = self.number_operands(operator, &left, &right)?;
+                Ok(Object::Num(l * r))
+            }
+            TokenType::GREATER => {
+                let (l, r) = self.number_operands(operator, &left, &right)?;
+                Ok(Object::Bool(l > r))
+            }
+            TokenType::GREATER_EQUAL => {
+                let (l, r) = self.number_operands(operator, &left, &right)?;
+                Ok(Object::Bool(l >= r))
             }
+            TokenType::LESS => {
+                let (l, r) = self.number_operands(operator, &left, &right)?;
+                Ok(Object::Bool(l < r))
+            }
+            TokenType::LESS_EQUAL => {
+                let (l, r) = self.number_operands(operator, &left, &right)?;
+                Ok(Object::Bool(l <= r))
+            }
+            TokenType::BANG_EQUAL => Ok(Object::Bool(left != right)),
+            TokenType::EQUAL_EQUAL => Ok(Object::Bool(left == right)),
+            _ => Err(LoxError::new_runtime(
+                operator,
+                Message::UnknownBinaryOperator(operator.lexeme.clone()),
+            )),
+        }
+    }
+
+    fn visit_call_expr(&self, expr: &CallExpr) -> Result<Object, LoxError> {
+        let callee = self.evaluate(&expr.callee)?;
+
+        let mut arguments = vec![];
+        for argument in &expr.arguments {
+            arguments.push(self.evaluate(argument)?);
+        }
+
+        let function: &dyn LoxCallable = match &callee {
+            Object::Callable(function) => function.as_ref(),
+            Object::Class(class) => class,
+            _ => return Err(LoxError::new_runtime(&expr.paren, Message::NotCallable)),
+        };
+        if arguments.len() != function.arity() {
+            return Err(LoxError::new_runtime(
+                &expr.paren,
+                Message::ArityMismatch {
+                    expected: function.arity(),
+                    got: arguments.len(),
+                },
+            ));
+        }
+        let depth = self.call_depth.get();
+        if depth >= MAX_CALL_DEPTH {
+            return Err(LoxError::new_runtime(&expr.paren, Message::StackOverflow));
+        }
+        self.call_depth.set(depth + 1);
+        let result = function.call(self, arguments);
+        self.call_depth.set(depth);
+        result.map_err(|err| match err {
+            LoxError::Runtime(mut err) => {
+                err.trace.push(StackFrame {
+                    function: function.name().to_string(),
+                    line: expr.paren.line,
+                });
+                LoxError::Runtime(err)
+            }
+            err => err,
+        })
+    }
+
+    fn visit_get_expr(&self, expr: &GetExpr) -> Result<Object, LoxError> {
+        match self.evaluate(&expr.object)? {
+            Object::Instance(instance) => LoxInstance::get(&instance, &expr.name),
+            _ => Err(LoxError::new_runtime(
+                &expr.name,
+                Message::OnlyInstancesHaveProperties,
+            )),
         }
-        Err(LoxError::new_runtime(String::from(
-            "RuntimeError: Invalid binary expression.",
-        )))
     }
 
     fn visit_grouping_expr(&self, expr: &GroupingExpr) -> Result<Object, LoxError> {
         self.evaluate(&expr.expression)
     }
 
+    fn visit_interpolation_expr(&self, expr: &InterpolationExpr) -> Result<Object, LoxError> {
+        let mut str = String::new();
+        for part in &expr.parts {
+            let value = self.evaluate(part)?;
+            str.push_str(&self.stringify(&value));
+        }
+        Ok(Object::Str(str))
+    }
+
     fn visit_literal_expr(&self, expr: &LiteralExpr) -> Result<Object, LoxError> {
         Ok(expr.value.clone())
     }
 
-    fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<Object, LoxError> {
-        let right = self.evaluate(&expr.right).unwrap();
+    fn visit_logical_expr(&self, expr: &LogicalExpr) -> Result<Object, LoxError> {
+        let left = self.evaluate(&expr.left)?;
 
-        if expr.operator.tty == TokenType::MINUS {
-            if let Object::Num(num) = right {
-                return Ok(Object::Num(-num));
+        if expr.operator.tty == TokenType::OR {
+            if self.is_truthy(&left) {
+                return Ok(left);
             }
+        } else if !self.is_truthy(&left) {
+            return Ok(left);
         }
-        if expr.operator.tty == TokenType::BANG {
-            if let Object::False = right {
-                return Ok(Object::True);
-            }
-            if let Object::True = right {
-                return Ok(Object::False);
-            }
+
+        self.evaluate(&expr.right)
+    }
+
+    fn visit_set_expr(&self, expr: &SetExpr) -> Result<Object, LoxError> {
+        let Object::Instance(instance) = self.evaluate(&expr.object)? else {
+            return Err(LoxError::new_runtime(
+                &expr.name,
+                Message::OnlyInstancesHaveFields,
+            ));
+        };
+        let value = self.evaluate(&expr.value)?;
+        instance.set(&expr.name, value.clone());
+        Ok(value)
+    }
+
+    fn visit_super_expr(&self, expr: &SuperExpr) -> Result<Object, LoxError> {
+        // `this` is always bound in the scope just inside the one holding `super`.
+        let distance = expr.depth.get().unwrap_or_default();
+        let env = self.env.borrow().clone();
+        let Some(Object::Class(superclass)) = env.borrow().get_at(distance, "super") else {
+            return Err(LoxError::new_runtime(
+                &expr.keyword,
+                Message::SuperclassMustBeClass,
+            ));
+        };
+        let Some(Object::Instance(instance)) =
+            env.borrow().get_at(distance.saturating_sub(1), "this")
+        else {
+            return Err(LoxError::new_runtime(
+                &expr.keyword,
+                Message::SuperOutsideMethod,
+            ));
+        };
+
+        match superclass.find_method(&expr.method.lexeme) {
+            Some(method) => Ok(Object::Callable(Rc::new(method.bind(instance)))),
+            None => Err(LoxError::new_runtime(
+                &expr.method,
+                Message::UndefinedProperty(expr.method.lexeme.clone()),
+            )),
+        }
+    }
+
+    fn visit_this_expr(&self, expr: &ThisExpr) -> Result<Object, LoxError> {
+        self.look_up_variable(&expr.keyword, &expr.depth)
+    }
+
+    fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<Object, LoxError> {
+        let right = self.evaluate(&expr.right)?;
+
+        match expr.operator.tty {
+            TokenType::MINUS => match right {
+                Object::Num(num) => Ok(Object::Num(-num)),
+                _ => Err(LoxError::new_runtime(
+                    &expr.operator,
+                    Message::OperandMustBeNumber,
+                )),
+            },
+            TokenType::BANG => Ok(Object::Bool(!self.is_truthy(&right))),
+            _ => Err(LoxError::new_runtime(
+                &expr.operator,
+                Message::UnknownUnaryOperator(expr.operator.lexeme.clone()),
+            )),
         }
-        Err(LoxError::new_runtime(String::from(
-            "RuntimeError: The expression after mius expected boolean.",
-        )))
     }
 
-    fn visit_variable_expr(&self, unary_expr: &VariableExpr) -> Result<Object, LoxError> {
-        self.env.get(&unary_expr.name)
+    fn visit_variable_expr(&self, expr: &VariableExpr) -> Result<Object, LoxError> {
+        self.look_up_variable(&expr.name, &expr.depth)
     }
 }
 
-impl StmtVisitor<()> for Interpreter {
-    fn visit_expression_stmt(&self, stmt: &ExpressionStmt) -> Result<(), LoxError> {
+impl StmtVisitor<(), Unwind> for Interpreter {
+    fn visit_block_stmt(&self, stmt: &BlockStmt) -> Result<(), Unwind> {
+        let env = Environment::new_enclosing(self.env.borrow().clone());
+        self.execute_block(&stmt.statements, env)
+    }
+
+    fn visit_class_stmt(&self, stmt: &ClassStmt) -> Result<(), Unwind> {
+        let superclass = match &stmt.superclass {
+            Some(superclass) => match self.visit_variable_expr(superclass)? {
+                Object::Class(class) => Some(class),
+                _ => {
+                    return Err(LoxError::new_runtime(
+                        &superclass.name,
+                        Message::SuperclassMustBeClass,
+                    )
+                    .into())
+                }
+            },
+            None => None,
+        };
+
+        self.env
+            .borrow()
+            .borrow_mut()
+            .define(stmt.name.lexeme.clone(), Object::Nil);
+
+        // Methods of a subclass close over an extra scope that binds `super`.
+        let mut closure = self.env.borrow().clone();
+        if let Some(superclass) = &superclass {
+            let mut env = Environment::new_enclosing(closure);
+            env.define(String::from("super"), Object::Class(superclass.clone()));
+            closure = Rc::new(RefCell::new(env));
+        }
+
+        let mut methods = HashMap::new();
+        for method in &stmt.methods {
+            let function = LoxFunction::new(
+                method.clone(),
+                closure.clone(),
+                method.name.lexeme == "init",
+            );
+            methods.insert(method.name.lexeme.clone(), Rc::new(function));
+        }
+
+        let class = LoxClass::new(stmt.name.lexeme.clone(), superclass, methods);
+        self.env
+            .borrow()
+            .borrow_mut()
+            .assign(&stmt.name, Object::Class(Rc::new(class)))?;
+        Ok(())
+    }
+
+    fn visit_expression_stmt(&self, stmt: &ExpressionStmt) -> Result<(), Unwind> {
         self.evaluate(&stmt.expression)?;
         Ok(())
     }
 
-    fn visit_print_stmt(&self, stmt: &PrintStmt) -> Result<(), LoxError> {
+    fn visit_function_stmt(&self, stmt: &FunctionStmt) -> Result<(), Unwind> {
+        let function = LoxFunction::new(stmt.clone(), self.env.borrow().clone(), false);
+        self.env.borrow().borrow_mut().define(
+            stmt.name.lexeme.clone(),
+            Object::Callable(Rc::new(function)),
+        );
+        Ok(())
+    }
+
+    fn visit_if_stmt(&self, stmt: &IfStmt) -> Result<(), Unwind> {
+        if self.is_truthy(&self.evaluate(&stmt.condition)?) {
+            self.execute(&stmt.then_branch)
+        } else if let Some(else_branch) = &stmt.else_branch {
+            self.execute(else_branch)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn visit_print_stmt(&self, stmt: &PrintStmt) -> Result<(), Unwind> {
         let value = self.evaluate(&stmt.expression)?;
-        println!("{}", self.stringify(&value));
+        // Writing fails if stdout was closed, e.g. piped into `head`.
+        writeln!(io::stdout(), "{}", self.stringify(&value)).map_err(LoxError::from)?;
         Ok(())
     }
 
-    fn visit_var_stmt(&mut self, stmt: &VarStmt) -> Result<(), LoxError> {
+    fn visit_return_stmt(&self, stmt: &ReturnStmt) -> Result<(), Unwind> {
+        let value = match &stmt.value {
+            Some(value) => self.evaluate(value)?,
+            None => Object::Nil,
+        };
+        Err(Unwind::Return(value))
+    }
+
+    fn visit_var_stmt(&self, stmt: &VarStmt) -> Result<(), Unwind> {
         let mut value = Object::Nil;
-        if stmt.initializer.is_some() {
-            let ini = stmt.initializer.as_ref().clone().unwrap();
-            value = self.evaluate(&ini)?;
+        if let Some(ini) = &stmt.initializer {
+            value = self.evaluate(ini)?;
         }
 
-        self.env.define(stmt.name.lexeme.clone(), value);
+        self.env
+            .borrow()
+            .borrow_mut()
+            .define(stmt.name.lexeme.clone(), value);
+        Ok(())
+    }
+
+    fn visit_while_stmt(&self, stmt: &WhileStmt) -> Result<(), Unwind> {
+        while self.is_truthy(&self.evaluate(&stmt.condition)?) {
+            self.execute(&stmt.body)?;
+        }
         Ok(())
     }
 }
@@ -133,33 +377,127 @@ impl StmtVisitor<()> for Interpreter {
 impl Interpreter {
     fn evaluate(&self, expr: &Expr) -> Result<Object, LoxError> {
         match expr {
+            Expr::Assign(n) => n.accept(self),
             Expr::Binary(n) => n.accept(self),
+            Expr::Call(n) => n.accept(self),
+            Expr::Get(n) => n.accept(self),
             Expr::Grouping(n) => n.accept(self),
+            Expr::Interpolation(n) => n.accept(self),
             Expr::Literal(n) => n.accept(self),
+            Expr::Logical(n) => n.accept(self),
+            Expr::Set(n) => n.accept(self),
+            Expr::Super(n) => n.accept(self),
+            Expr::This(n) => n.accept(self),
             Expr::Unary(n) => n.accept(self),
             Expr::Variable(n) => n.accept(self),
         }
     }
     pub fn new() -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().define(
+            String::from("clock"),
+            Object::Callable(Rc::new(NativeFunction {
+                name: String::from("clock"),
+                arity: 0,
+                func: callable::clock,
+            })),
+        );
+
         Self {
-            env: Environment::new(),
+            env: RefCell::new(globals.clone()),
+            globals,
+            call_depth: Cell::new(0),
         }
     }
     pub fn interpret(&mut self, statements: &Vec<Stmt>) -> Result<(), LoxError> {
         for statement in statements {
-            self.execute(statement)?;
+            match self.execute(statement) {
+                Ok(()) => {}
+                Err(Unwind::Error(err)) => return Err(err),
+                // The resolver rejects `return` outside of a function.
+                Err(Unwind::Return(_)) => break,
+            }
         }
         Ok(())
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<(), LoxError> {
+    /// Evaluates `expr` and renders its value the way `print` would.
+    pub fn interpret_expression(&mut self, expr: &Expr) -> Result<String, LoxError> {
+        let value = self.evaluate(expr)?;
+        Ok(self.stringify(&value))
+    }
+
+    /// Every global variable with its value, sorted by name.
+    pub fn globals(&self) -> Vec<(String, String)> {
+        let mut globals: Vec<_> = self
+            .globals
+            .borrow()
+            .values()
+            .map(|(name, value)| (name.clone(), self.stringify(value)))
+            .collect();
+        globals.sort();
+        globals
+    }
+
+    fn execute(&self, stmt: &Stmt) -> Result<(), Unwind> {
         match stmt {
+            Stmt::BlockStmt(n) => n.accept(self),
+            Stmt::ClassStmt(n) => n.accept(self),
             Stmt::ExpressionStmt(n) => n.accept(self),
+            Stmt::FunctionStmt(n) => n.accept(self),
+            Stmt::IfStmt(n) => n.accept(self),
             Stmt::PrintStmt(n) => n.accept(self),
+            Stmt::ReturnStmt(n) => n.accept(self),
             Stmt::VarStmt(n) => n.accept(self),
+            Stmt::WhileStmt(n) => n.accept(self),
+        }
+    }
+
+    pub fn execute_block(&self, statements: &[Stmt], env: Environment) -> Result<(), Unwind> {
+        let previous = self.env.replace(Rc::new(RefCell::new(env)));
+        let result = statements.iter().try_for_each(|stmt| self.execute(stmt));
+        self.env.replace(previous);
+        result
+    }
+
+    fn look_up_variable(
+        &self,
+        name: &Token,
+        depth: &Cell<Option<usize>>,
+    ) -> Result<Object, LoxError> {
+        match depth.get() {
+            Some(distance) => self
+                .env
+                .borrow()
+                .borrow()
+                .get_at(distance, &name.lexeme)
+                .ok_or_else(|| {
+                    LoxError::new_runtime(name, Message::UndefinedVariable(name.lexeme.clone()))
+                }),
+            None => self.globals.borrow().get(name),
         }
     }
 
+    fn number_operands(
+        &self,
+        operator: &Token,
+        left: &Object,
+        right: &Object,
+    ) -> Result<(f64, f64), LoxError> {
+        match (left, right) {
+            (Object::Num(l), Object::Num(r)) => Ok((*l, *r)),
+            _ => Err(LoxError::new_runtime(
+                operator,
+                Message::OperandsMustBeNumbers,
+            )),
+        }
+    }
+
+    /// `nil` and `false` are falsey; every other value is truthy.
+    fn is_truthy(&self, obj: &Object) -> bool {
+        !matches!(obj, Object::Nil | Object::Bool(false))
+    }
+
     fn stringify(&self, obj: &Object) -> String {
         match obj {
             Object::Num(n) => {
@@ -167,12 +505,14 @@ impl Interpreter {
                 if str.ends_with(".0") {
                     return str[0..str.len() - 2].to_string();
                 }
-                return str;
+                str
             }
             Object::Str(s) => s.clone(),
             Object::Nil => String::from("nil"),
-            Object::True => String::from("true"),
-            Object::False => String::from("false"),
+            Object::Bool(b) => b.to_string(),
+            Object::Callable(c) => c.to_string(),
+            Object::Class(c) => c.to_string(),
+            Object::Instance(i) => i.to_string(),
         }
     }
 }
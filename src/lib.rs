@@ -1,10 +1,18 @@
 pub mod lox;
-mod utils;
+pub mod span;
 mod scanner;
 mod token;
-mod error;
+pub mod error;
+pub mod diagnostic;
+pub mod message;
 mod macros;
 mod ast;
+mod ast_printer;
 mod parser;
 mod interpreter;
-mod environment;
\ No newline at end of file
+mod environment;
+mod resolver;
+mod callable;
+mod function;
+mod class;
+mod instance;
\ No newline at end of file
@@ -1,41 +1,345 @@
-use crate::{error::LoxError, interpreter::Interpreter, parser::Parser, scanner::Scanner};
+use crate::{
+    ast::{Expr, Stmt},
+    ast_printer::AstPrinter,
+    diagnostic::{Diagnostic, Format},
+    error::LoxError,
+    interpreter::Interpreter,
+    message::{Lang, Message},
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+    span::Span,
+};
+
+/// Code `Lox` has compiled. A function can fail long after the code that
+/// defined it ran, so the code is kept to show such errors against.
+struct Source {
+    name: String,
+    text: String,
+}
 
 pub struct Lox {
+    /// Set once a compile error, I/O error or unloadable syntax tree has been
+    /// reported.
     pub had_error: bool,
+    /// Set once a runtime error has been reported.
+    pub had_runtime_error: bool,
+    /// How `report` prints diagnostics.
+    pub format: Format,
+    /// Language of diagnostic messages. Defaults to the one `LANG` names.
+    pub lang: Lang,
     scanner: Scanner,
     interpreter: Interpreter,
     parser: Parser,
+    /// Everything compiled since the last `reset`. A span's `source` is its
+    /// index here plus one.
+    sources: Vec<Source>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Lox {
     pub fn new() -> Lox {
         Self {
             had_error: false,
+            had_runtime_error: false,
+            format: Format::default(),
+            lang: Lang::from_env(),
             scanner: Scanner::new(),
             interpreter: Interpreter::new(),
             parser: Parser::new(),
+            sources: vec![],
         }
     }
 
-    pub fn error(line: usize, message: String) -> LoxError {
-        LoxError::new_compile(message, line)
+    /// Prints `err` to stderr, quoting the code it came from under the name
+    /// that code was compiled with. Errors that don't point into compiled
+    /// code, such as I/O errors and errors in a tree loaded by `run_ast`,
+    /// are reported under `file`, by line and column alone if they have
+    /// them.
+    pub fn report(&mut self, err: &LoxError, file: &str) {
+        self.record(err);
+        let diagnostic = Diagnostic::from_error(err, self.lang);
+        let (file, source) = match err.span().and_then(|span| self.source_of(span)) {
+            Some(source) => (source.name.as_str(), Some(source.text.as_str())),
+            None => (file, None),
+        };
+        eprint!("{}", diagnostic.render(file, source, self.format));
+    }
+
+    /// The compiled code `span` points into, if it is still kept and the
+    /// span fits inside it.
+    fn source_of(&self, span: Span) -> Option<&Source> {
+        let source = self.sources.get(span.source.checked_sub(1)?)?;
+        source.text.get(span.start..span.end)?;
+        Some(source)
     }
 
-    pub fn report(err: LoxError, loc: String) {
+    /// Keeps `text` for reporting errors, returning the number its spans are
+    /// to carry.
+    fn add_source(&mut self, name: &str, text: &str) -> usize {
+        self.sources.push(Source {
+            name: name.to_string(),
+            text: text.to_string(),
+        });
+        self.sources.len()
+    }
+
+    /// Sets the flag `err` calls for.
+    fn record(&mut self, err: &LoxError) {
         match err {
-            LoxError::Compile(err) => {
-                eprintln!("[line {}] Error {loc}: {}", err.line, err.msg)
+            LoxError::Runtime(_) => self.had_runtime_error = true,
+            _ => self.had_error = true,
+        }
+    }
+
+    /// Runs `source`, returning every scan and parse error at once. Tokens
+    /// are still parsed after a scan error so the parser can report too.
+    /// `file` names the source when its errors are reported, here and in the
+    /// other methods that take one.
+    pub fn run(&mut self, file: &str, source: String) -> Result<(), Vec<LoxError>> {
+        let id = self.add_source(file, &source);
+        let stmts = self.compile(source, id)?;
+        self.interpreter.interpret(&stmts).map_err(|err| vec![err])
+    }
+
+    /// Reports the errors `run` would find before running anything, without
+    /// executing `source`.
+    pub fn check(&mut self, file: &str, source: String) -> Result<(), Vec<LoxError>> {
+        let id = self.add_source(file, &source);
+        self.compile(source, id).map(|_| ())
+    }
+
+    /// Runs one line of REPL input. A lone expression may leave out the `;`,
+    /// and its value is returned for the REPL to print; anything else runs
+    /// exactly as `run` would.
+    pub fn run_repl(
+        &mut self,
+        file: &str,
+        source: String,
+    ) -> Result<Option<String>, Vec<LoxError>> {
+        let id = self.add_source(file, &source);
+        let Some(expr) = self.parse_expression(&source, id) else {
+            let stmts = self.compile(source, id)?;
+            return self
+                .interpreter
+                .interpret(&stmts)
+                .map(|()| None)
+                .map_err(|err| vec![err]);
+        };
+        Resolver::new().resolve_expression(&expr)?;
+        self.interpreter
+            .interpret_expression(&expr)
+            .map(Some)
+            .map_err(|err| vec![err])
+    }
+
+    /// Every global variable with its value, sorted by name.
+    pub fn globals(&self) -> Vec<(String, String)> {
+        self.interpreter.globals()
+    }
+
+    /// Forgets every variable, function and class defined so far.
+    pub fn reset(&mut self) {
+        self.interpreter = Interpreter::new();
+        self.sources.clear();
+    }
+
+    /// The tokens `source` scans to, one per line, each preceded by the
+    /// line and column where it starts.
+    pub fn dump_tokens(&mut self, file: &str, source: String) -> Result<String, Vec<LoxError>> {
+        let id = self.add_source(file, &source);
+        let (tokens, errors) = self.scanner.scan_tokens(source, id);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(tokens
+            .iter()
+            .map(|token| format!("{}:{} {token}\n", token.line, token.column))
+            .collect())
+    }
+
+    /// The syntax tree `source` parses to, printed by `AstPrinter` with one
+    /// top-level statement per line. Like `run_repl`, this accepts a lone
+    /// expression without a `;`.
+    pub fn dump_ast(&mut self, file: &str, source: String) -> Result<String, Vec<LoxError>> {
+        let id = self.add_source(file, &source);
+        if let Some(expr) = self.parse_expression(&source, id) {
+            return Ok(format!("{}\n", AstPrinter.print_expr(&expr)));
+        }
+        let printer = AstPrinter;
+        Ok(self
+            .parse(source, id)?
+            .iter()
+            .map(|stmt| format!("{}\n", printer.print_stmt(stmt)))
+            .collect())
+    }
+
+    /// The syntax tree `source` parses to, as a JSON array of statements
+    /// that `run_ast` can load again.
+    pub fn dump_ast_json(&mut self, file: &str, source: String) -> Result<String, Vec<LoxError>> {
+        let id = self.add_source(file, &source);
+        let stmts = self.parse(source, id)?;
+        let mut json =
+            serde_json::to_string_pretty(&stmts).expect("a parsed syntax tree always serializes");
+        json.push('\n');
+        Ok(json)
+    }
+
+    /// Resolves and runs a syntax tree in the JSON form `dump_ast_json`
+    /// produces.
+    pub fn run_ast(&mut self, json: &str) -> Result<(), Vec<LoxError>> {
+        let stmts: Vec<Stmt> =
+            serde_json::from_str(json).map_err(|err| vec![LoxError::Load(err)])?;
+        Resolver::new().resolve(&stmts)?;
+        self.interpreter.interpret(&stmts).map_err(|err| vec![err])
+    }
+
+    /// Whether `source` stops partway through a statement, inside an open
+    /// block, string or comment for instance, so that more lines could
+    /// complete it. Input that is already wrong before its end counts as
+    /// complete, so its errors get reported straight away.
+    pub fn is_incomplete(&mut self, source: &str) -> bool {
+        // Nothing here runs or gets reported, so the source isn't kept.
+        if self.parse_expression(source, 0).is_some() {
+            return false;
+        }
+        let (tokens, errors) = self.scanner.scan_tokens(source.to_string(), 0);
+        if let Some(err) = errors.first() {
+            return matches!(
+                err,
+                LoxError::Scan(err)
+                    if matches!(err.msg, Message::UnterminatedString | Message::UnterminatedBlockComment)
+            );
+        }
+        match self.parser.parse(tokens) {
+            Ok(_) => false,
+            Err(errors) => {
+                matches!(errors.first(), Some(LoxError::Parse(err)) if err.span.start == source.len())
             }
-            LoxError::Runtime(err) => {
-                eprintln!("[runtime error] Error {loc}: {}", err.msg)
+        }
+    }
+
+    /// `source` as a single expression, if it is one and scans cleanly.
+    fn parse_expression(&mut self, source: &str, id: usize) -> Option<Expr> {
+        let (tokens, errors) = self.scanner.scan_tokens(source.to_string(), id);
+        if !errors.is_empty() {
+            return None;
+        }
+        self.parser.parse_expression(tokens).ok()
+    }
+
+    fn compile(&mut self, source: String, id: usize) -> Result<Vec<Stmt>, Vec<LoxError>> {
+        let stmts = self.parse(source, id)?;
+        Resolver::new().resolve(&stmts)?;
+        Ok(stmts)
+    }
+
+    /// Scan and parse errors come back together, in the order they appear
+    /// in `source`.
+    fn parse(&mut self, source: String, id: usize) -> Result<Vec<Stmt>, Vec<LoxError>> {
+        let (tokens, mut errors) = self.scanner.scan_tokens(source, id);
+        match self.parser.parse(tokens) {
+            Ok(stmts) if errors.is_empty() => Ok(stmts),
+            Ok(_) => Err(errors),
+            Err(parse_errors) => {
+                // Each list is already in order; this interleaves them.
+                errors.extend(parse_errors);
+                errors.sort_by_key(|err| err.span().map(|span| span.start));
+                Err(errors)
             }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uses every kind of statement and expression.
+    const PROGRAM: &str = r#"
+/// A named shape.
+class Shape {
+  init(name) { this.name = name; }
+  describe() { return "shape ${this.name}"; }
+}
+class Square < Shape {
+  describe() { return super.describe() + "!"; }
+}
+/// Doubles and sums 1 to n.
+fun count(n) {
+  var total = 0;
+  while (n > 0 and !false) {
+    total = total + (n * 2);
+    n = n - 1;
+  }
+  return total;
+}
+var result;
+{
+  if (count(3) == 12 or nil) result = Square("a").describe(); else result = -1;
+}
+print result;
+"#;
+
+    #[test]
+    fn json_syntax_tree_round_trips() {
+        let mut lox = Lox::new();
+        let json = lox.dump_ast_json("a.lox", PROGRAM.to_string()).unwrap();
+        for name in [
+            "BlockStmt",
+            "ClassStmt",
+            "ExpressionStmt",
+            "FunctionStmt",
+            "IfStmt",
+            "PrintStmt",
+            "ReturnStmt",
+            "VarStmt",
+            "WhileStmt",
+            "Assign",
+            "Binary",
+            "Call",
+            "Get",
+            "Grouping",
+            "Interpolation",
+            "Literal",
+            "Logical",
+            "Set",
+            "Super",
+            "This",
+            "Unary",
+            "Variable",
+        ] {
+            assert!(json.contains(&format!("\"type\": \"{name}\"")), "no {name}");
+        }
+
+        assert!(json.contains("\"doc\": \"A named shape.\""));
+
+        let stmts: Vec<Stmt> = serde_json::from_str(&json).unwrap();
+        let again = serde_json::to_string_pretty(&stmts).unwrap() + "\n";
+        assert_eq!(json, again);
+
+        let mut from_json = Lox::new();
+        from_json.run_ast(&json).unwrap();
+        let mut from_source = Lox::new();
+        from_source.run("a.lox", PROGRAM.to_string()).unwrap();
+        assert_eq!(from_json.globals(), from_source.globals());
+        assert!(from_json
+            .globals()
+            .contains(&("result".to_string(), "shape a!".to_string())));
+    }
 
-    pub fn run(&mut self, source: String) -> Result<(), LoxError> {
-        let tokens = self.scanner.scan_tokens(source)?.clone();
-        let stmts = self.parser.parse(tokens)?;
-        self.interpreter.interpret(&stmts)?;
-        Ok(())
+    #[test]
+    fn readme_json_example_runs() {
+        let readme = include_str!("../README.md");
+        let example = readme.split("`print 1 + 2;` 可以写作").nth(1).unwrap();
+        let json = example.split("```json").nth(1).unwrap();
+        let json = json.split("```").next().unwrap();
+        let mut lox = Lox::new();
+        lox.run_ast(json).unwrap();
     }
 }
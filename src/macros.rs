@@ -2,6 +2,7 @@
 macro_rules! hashmap {
     ($( $key:expr => $value:expr ),*) => {
         {
+            #[allow(unused_mut)]
             let mut temp_map = ::std::collections::HashMap::new();
             $(
                 temp_map.insert($key, $value);
@@ -0,0 +1,415 @@
+use std::{env, str::FromStr};
+
+use crate::diagnostic::ErrorCode;
+
+/// Language diagnostics are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    ZhCn,
+}
+
+impl Lang {
+    /// Chinese if `LANG` names a Chinese locale, such as `zh_CN.UTF-8`,
+    /// and English otherwise.
+    pub fn from_env() -> Lang {
+        env::var("LANG")
+            .ok()
+            .and_then(|lang| lang.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lang = s.split('.').next().unwrap_or(s).to_ascii_lowercase();
+        if lang == "en" || lang.starts_with("en_") || lang.starts_with("en-") || lang == "c" {
+            Ok(Lang::En)
+        } else if lang == "zh" || lang.starts_with("zh_") || lang.starts_with("zh-") {
+            Ok(Lang::ZhCn)
+        } else {
+            Err(format!("unsupported language '{s}'"))
+        }
+    }
+}
+
+/// Whether a function being parsed is a free function or a method, for
+/// messages that name the kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionKind {
+    Function,
+    Method,
+}
+
+impl FunctionKind {
+    fn text(self, lang: Lang) -> &'static str {
+        match (self, lang) {
+            (FunctionKind::Function, Lang::En) => "function",
+            (FunctionKind::Method, Lang::En) => "method",
+            (FunctionKind::Function, Lang::ZhCn) => "函数",
+            (FunctionKind::Method, Lang::ZhCn) => "方法",
+        }
+    }
+}
+
+/// Every error message Lox can report. Errors carry one of these instead of
+/// a finished string so they can be shown in the user's language.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    UnterminatedString,
+    UnterminatedBlockComment,
+    UnexpectedCharacter,
+    /// The escape sequence as written, such as `\q`.
+    InvalidEscape(String),
+    InvalidUnicodeEscape(String),
+    /// The literal as written, such as `0b102`.
+    MalformedNumber(String),
+    NumberTooLarge(String),
+
+    ExpectClassName,
+    ExpectSuperclassName,
+    ExpectLeftBraceBeforeClassBody,
+    ExpectRightBraceAfterClassBody,
+    ExpectFunctionName(FunctionKind),
+    ExpectLeftParenAfterFunctionName(FunctionKind),
+    ExpectParameterName,
+    ExpectRightParenAfterParameters,
+    ExpectLeftBraceBeforeFunctionBody(FunctionKind),
+    ExpectVariableName,
+    ExpectSemicolonAfterVariableDeclaration,
+    /// The keyword, such as `if`, that must be followed by '('.
+    ExpectLeftParenAfter(&'static str),
+    ExpectSemicolonAfterLoopCondition,
+    ExpectRightParenAfterForClauses,
+    ExpectRightParenAfterIfCondition,
+    ExpectRightParenAfterCondition,
+    ExpectSemicolonAfterReturnValue,
+    ExpectRightBraceAfterBlock,
+    ExpectSemicolonAfterValue,
+    ExpectPropertyName,
+    ExpectRightParenAfterArguments,
+    ExpectDotAfterSuper,
+    ExpectSuperclassMethodName,
+    ExpectRightParenAfterExpression,
+    ExpectRightBraceAfterInterpolation,
+    ExpectExpression,
+    InvalidAssignmentTarget,
+    TooManyArguments(usize),
+    TooManyParameters(usize),
+    InheritFromSelf,
+
+    ReadInOwnInitializer,
+    AlreadyDeclared,
+    TopLevelReturn,
+    ReturnFromInitializer,
+    ThisOutsideClass,
+    SuperOutsideClass,
+    SuperWithoutSuperclass,
+
+    OperandMustBeNumber,
+    OperandsMustBeNumbers,
+    OperandsMustBeNumbersOrStrings,
+    UndefinedVariable(String),
+    UndefinedProperty(String),
+    OnlyInstancesHaveProperties,
+    OnlyInstancesHaveFields,
+    NotCallable,
+    ArityMismatch {
+        expected: usize,
+        got: usize,
+    },
+    StackOverflow,
+    SuperclassMustBeClass,
+    SuperOutsideMethod,
+    UnknownBinaryOperator(String),
+    UnknownUnaryOperator(String),
+
+    /// The operating system's description of an I/O failure.
+    Io(String),
+    FileNotFound,
+    PermissionDenied,
+    /// Byte offset of the first byte that isn't valid UTF-8.
+    InvalidUtf8(usize),
+    /// What the JSON parser objected to, without its position.
+    InvalidSyntaxTree(String),
+}
+
+impl Message {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Message::UnterminatedString => ErrorCode::UnterminatedString,
+            Message::UnterminatedBlockComment => ErrorCode::UnterminatedBlockComment,
+            Message::UnexpectedCharacter => ErrorCode::UnexpectedCharacter,
+            Message::InvalidEscape(_) | Message::InvalidUnicodeEscape(_) => {
+                ErrorCode::InvalidEscape
+            }
+            Message::MalformedNumber(_) | Message::NumberTooLarge(_) => ErrorCode::MalformedNumber,
+            Message::ExpectClassName
+            | Message::ExpectSuperclassName
+            | Message::ExpectLeftBraceBeforeClassBody
+            | Message::ExpectRightBraceAfterClassBody
+            | Message::ExpectFunctionName(_)
+            | Message::ExpectLeftParenAfterFunctionName(_)
+            | Message::ExpectParameterName
+            | Message::ExpectRightParenAfterParameters
+            | Message::ExpectLeftBraceBeforeFunctionBody(_)
+            | Message::ExpectVariableName
+            | Message::ExpectSemicolonAfterVariableDeclaration
+            | Message::ExpectLeftParenAfter(_)
+            | Message::ExpectSemicolonAfterLoopCondition
+            | Message::ExpectRightParenAfterForClauses
+            | Message::ExpectRightParenAfterIfCondition
+            | Message::ExpectRightParenAfterCondition
+            | Message::ExpectSemicolonAfterReturnValue
+            | Message::ExpectRightBraceAfterBlock
+            | Message::ExpectSemicolonAfterValue
+            | Message::ExpectPropertyName
+            | Message::ExpectRightParenAfterArguments
+            | Message::ExpectDotAfterSuper
+            | Message::ExpectSuperclassMethodName
+            | Message::ExpectRightParenAfterExpression
+            | Message::ExpectRightBraceAfterInterpolation => ErrorCode::ExpectedToken,
+            Message::ExpectExpression => ErrorCode::ExpectedExpression,
+            Message::InvalidAssignmentTarget => ErrorCode::InvalidAssignmentTarget,
+            Message::TooManyArguments(_) => ErrorCode::TooManyArguments,
+            Message::TooManyParameters(_) => ErrorCode::TooManyParameters,
+            Message::InheritFromSelf => ErrorCode::InheritFromSelf,
+            Message::ReadInOwnInitializer => ErrorCode::ReadInOwnInitializer,
+            Message::AlreadyDeclared => ErrorCode::AlreadyDeclared,
+            Message::TopLevelReturn => ErrorCode::TopLevelReturn,
+            Message::ReturnFromInitializer => ErrorCode::ReturnFromInitializer,
+            Message::ThisOutsideClass => ErrorCode::ThisOutsideClass,
+            Message::SuperOutsideClass | Message::SuperOutsideMethod => {
+                ErrorCode::SuperOutsideClass
+            }
+            Message::SuperWithoutSuperclass => ErrorCode::SuperWithoutSuperclass,
+            Message::OperandMustBeNumber | Message::OperandsMustBeNumbers => {
+                ErrorCode::OperandNotNumber
+            }
+            Message::OperandsMustBeNumbersOrStrings => ErrorCode::InvalidPlusOperands,
+            Message::UndefinedVariable(_) => ErrorCode::UndefinedVariable,
+            Message::UndefinedProperty(_) => ErrorCode::UndefinedProperty,
+            Message::OnlyInstancesHaveProperties | Message::OnlyInstancesHaveFields => {
+                ErrorCode::NotAnInstance
+            }
+            Message::NotCallable => ErrorCode::NotCallable,
+            Message::ArityMismatch { .. } => ErrorCode::ArityMismatch,
+            Message::StackOverflow => ErrorCode::StackOverflow,
+            Message::SuperclassMustBeClass => ErrorCode::SuperclassNotClass,
+            Message::UnknownBinaryOperator(_) | Message::UnknownUnaryOperator(_) => {
+                ErrorCode::UnknownOperator
+            }
+            Message::Io(_)
+            | Message::FileNotFound
+            | Message::PermissionDenied
+            | Message::InvalidUtf8(_) => ErrorCode::Io,
+            Message::InvalidSyntaxTree(_) => ErrorCode::InvalidSyntaxTree,
+        }
+    }
+
+    pub fn text(&self, lang: Lang) -> String {
+        match lang {
+            Lang::En => self.en(),
+            Lang::ZhCn => self.zh_cn(),
+        }
+    }
+
+    fn en(&self) -> String {
+        match self {
+            Message::UnterminatedString => "Unterminated string.".into(),
+            Message::UnterminatedBlockComment => "Unterminated block comment.".into(),
+            Message::UnexpectedCharacter => "Unexpected character.".into(),
+            Message::InvalidEscape(seq) => format!("Invalid escape sequence '{seq}'."),
+            Message::InvalidUnicodeEscape(seq) => format!(
+                "Invalid Unicode escape '{seq}'. Expect 1 to 6 hex digits naming a character, \
+                 as in '\\u{{1F600}}'."
+            ),
+            Message::MalformedNumber(text) => format!("Malformed number literal '{text}'."),
+            Message::NumberTooLarge(text) => format!("Number literal '{text}' is too large."),
+            Message::ExpectClassName => "Expect class name.".into(),
+            Message::ExpectSuperclassName => "Expect superclass name.".into(),
+            Message::ExpectLeftBraceBeforeClassBody => "Expect '{' before class body.".into(),
+            Message::ExpectRightBraceAfterClassBody => "Expect '}' after class body.".into(),
+            Message::ExpectFunctionName(kind) => format!("Expect {} name.", kind.text(Lang::En)),
+            Message::ExpectLeftParenAfterFunctionName(kind) => {
+                format!("Expect '(' after {} name.", kind.text(Lang::En))
+            }
+            Message::ExpectParameterName => "Expect parameter name.".into(),
+            Message::ExpectRightParenAfterParameters => "Expect ')' after parameters.".into(),
+            Message::ExpectLeftBraceBeforeFunctionBody(kind) => {
+                format!("Expect '{{' before {} body.", kind.text(Lang::En))
+            }
+            Message::ExpectVariableName => "Expect variable name.".into(),
+            Message::ExpectSemicolonAfterVariableDeclaration => {
+                "Expect ';' after variable declaration.".into()
+            }
+            Message::ExpectLeftParenAfter(keyword) => format!("Expect '(' after '{keyword}'."),
+            Message::ExpectSemicolonAfterLoopCondition => "Expect ';' after loop condition.".into(),
+            Message::ExpectRightParenAfterForClauses => "Expect ')' after for clauses.".into(),
+            Message::ExpectRightParenAfterIfCondition => "Expect ')' after if condition.".into(),
+            Message::ExpectRightParenAfterCondition => "Expect ')' after condition.".into(),
+            Message::ExpectSemicolonAfterReturnValue => "Expect ';' after return value.".into(),
+            Message::ExpectRightBraceAfterBlock => "Expect '}' after block.".into(),
+            Message::ExpectSemicolonAfterValue => "Expect ';' after value.".into(),
+            Message::ExpectPropertyName => "Expect property name after '.'.".into(),
+            Message::ExpectRightParenAfterArguments => "Expect ')' after arguments.".into(),
+            Message::ExpectDotAfterSuper => "Expect '.' after 'super'.".into(),
+            Message::ExpectSuperclassMethodName => "Expect superclass method name.".into(),
+            Message::ExpectRightParenAfterExpression => "Expect ')' after expression.".into(),
+            Message::ExpectRightBraceAfterInterpolation => {
+                "Expect '}' after interpolated expression.".into()
+            }
+            Message::ExpectExpression => "Expect expression.".into(),
+            Message::InvalidAssignmentTarget => "Invalid assignment target.".into(),
+            Message::TooManyArguments(max) => format!("Can't have more than {max} arguments."),
+            Message::TooManyParameters(max) => format!("Can't have more than {max} parameters."),
+            Message::InheritFromSelf => "A class can't inherit from itself.".into(),
+            Message::ReadInOwnInitializer => {
+                "Can't read local variable in its own initializer.".into()
+            }
+            Message::AlreadyDeclared => "Already a variable with this name in this scope.".into(),
+            Message::TopLevelReturn => "Can't return from top-level code.".into(),
+            Message::ReturnFromInitializer => "Can't return a value from an initializer.".into(),
+            Message::ThisOutsideClass => "Can't use 'this' outside of a class.".into(),
+            Message::SuperOutsideClass => "Can't use 'super' outside of a class.".into(),
+            Message::SuperWithoutSuperclass => {
+                "Can't use 'super' in a class with no superclass.".into()
+            }
+            Message::OperandMustBeNumber => "Operand must be a number.".into(),
+            Message::OperandsMustBeNumbers => "Operands must be numbers.".into(),
+            Message::OperandsMustBeNumbersOrStrings => {
+                "Operands must be two numbers or two strings.".into()
+            }
+            Message::UndefinedVariable(name) => format!("Undefined variable '{name}'."),
+            Message::UndefinedProperty(name) => format!("Undefined property '{name}'."),
+            Message::OnlyInstancesHaveProperties => "Only instances have properties.".into(),
+            Message::OnlyInstancesHaveFields => "Only instances have fields.".into(),
+            Message::NotCallable => "Can only call functions and classes.".into(),
+            Message::ArityMismatch { expected, got } => {
+                format!("Expected {expected} arguments but got {got}.")
+            }
+            Message::StackOverflow => "Stack overflow.".into(),
+            Message::SuperclassMustBeClass => "Superclass must be a class.".into(),
+            Message::SuperOutsideMethod => "Can't use 'super' outside of a method.".into(),
+            Message::UnknownBinaryOperator(op) => format!("Unknown binary operator '{op}'."),
+            Message::UnknownUnaryOperator(op) => format!("Unknown unary operator '{op}'."),
+            Message::Io(detail) => format!("I/O error: {detail}"),
+            Message::FileNotFound => "No such file or directory.".into(),
+            Message::PermissionDenied => "Permission denied.".into(),
+            Message::InvalidUtf8(offset) => format!("Invalid UTF-8 at byte offset {offset}."),
+            Message::InvalidSyntaxTree(detail) => format!("Invalid syntax tree: {detail}."),
+        }
+    }
+
+    fn zh_cn(&self) -> String {
+        match self {
+            Message::UnterminatedString => "字符串未闭合。".into(),
+            Message::UnterminatedBlockComment => "块注释未闭合。".into(),
+            Message::UnexpectedCharacter => "无法识别的字符。".into(),
+            Message::InvalidEscape(seq) => format!("无效的转义序列 '{seq}'。"),
+            Message::InvalidUnicodeEscape(seq) => format!(
+                "无效的 Unicode 转义 '{seq}'。应为 1 到 6 位表示字符的十六进制数字，如 '\\u{{1F600}}'。"
+            ),
+            Message::MalformedNumber(text) => format!("格式错误的数字字面量 '{text}'。"),
+            Message::NumberTooLarge(text) => format!("数字字面量 '{text}' 过大。"),
+            Message::ExpectClassName => "此处应为类名。".into(),
+            Message::ExpectSuperclassName => "此处应为父类名。".into(),
+            Message::ExpectLeftBraceBeforeClassBody => "类体前应有 '{'。".into(),
+            Message::ExpectRightBraceAfterClassBody => "类体后应有 '}'。".into(),
+            Message::ExpectFunctionName(kind) => format!("此处应为{}名。", kind.text(Lang::ZhCn)),
+            Message::ExpectLeftParenAfterFunctionName(kind) => {
+                format!("{}名后应有 '('。", kind.text(Lang::ZhCn))
+            }
+            Message::ExpectParameterName => "此处应为参数名。".into(),
+            Message::ExpectRightParenAfterParameters => "参数列表后应有 ')'。".into(),
+            Message::ExpectLeftBraceBeforeFunctionBody(kind) => {
+                format!("{}体前应有 '{{'。", kind.text(Lang::ZhCn))
+            }
+            Message::ExpectVariableName => "此处应为变量名。".into(),
+            Message::ExpectSemicolonAfterVariableDeclaration => "变量声明后应有 ';'。".into(),
+            Message::ExpectLeftParenAfter(keyword) => format!("'{keyword}' 后应有 '('。"),
+            Message::ExpectSemicolonAfterLoopCondition => "循环条件后应有 ';'。".into(),
+            Message::ExpectRightParenAfterForClauses => "for 子句后应有 ')'。".into(),
+            Message::ExpectRightParenAfterIfCondition => "if 条件后应有 ')'。".into(),
+            Message::ExpectRightParenAfterCondition => "条件后应有 ')'。".into(),
+            Message::ExpectSemicolonAfterReturnValue => "返回值后应有 ';'。".into(),
+            Message::ExpectRightBraceAfterBlock => "代码块后应有 '}'。".into(),
+            Message::ExpectSemicolonAfterValue => "值后应有 ';'。".into(),
+            Message::ExpectPropertyName => "'.' 后应为属性名。".into(),
+            Message::ExpectRightParenAfterArguments => "实参列表后应有 ')'。".into(),
+            Message::ExpectDotAfterSuper => "'super' 后应有 '.'。".into(),
+            Message::ExpectSuperclassMethodName => "此处应为父类方法名。".into(),
+            Message::ExpectRightParenAfterExpression => "表达式后应有 ')'。".into(),
+            Message::ExpectRightBraceAfterInterpolation => "插值表达式后应有 '}'。".into(),
+            Message::ExpectExpression => "此处应为表达式。".into(),
+            Message::InvalidAssignmentTarget => "无效的赋值目标。".into(),
+            Message::TooManyArguments(max) => format!("实参不能超过 {max} 个。"),
+            Message::TooManyParameters(max) => format!("形参不能超过 {max} 个。"),
+            Message::InheritFromSelf => "类不能继承自身。".into(),
+            Message::ReadInOwnInitializer => "不能在局部变量自身的初始化式中读取它。".into(),
+            Message::AlreadyDeclared => "此作用域中已有同名变量。".into(),
+            Message::TopLevelReturn => "不能在顶层代码中 return。".into(),
+            Message::ReturnFromInitializer => "不能在初始化方法中返回值。".into(),
+            Message::ThisOutsideClass => "不能在类外使用 'this'。".into(),
+            Message::SuperOutsideClass => "不能在类外使用 'super'。".into(),
+            Message::SuperWithoutSuperclass => "不能在没有父类的类中使用 'super'。".into(),
+            Message::OperandMustBeNumber => "操作数必须是数字。".into(),
+            Message::OperandsMustBeNumbers => "操作数必须都是数字。".into(),
+            Message::OperandsMustBeNumbersOrStrings => "操作数必须同为数字或同为字符串。".into(),
+            Message::UndefinedVariable(name) => format!("未定义的变量 '{name}'。"),
+            Message::UndefinedProperty(name) => format!("未定义的属性 '{name}'。"),
+            Message::OnlyInstancesHaveProperties => "只有实例才有属性。".into(),
+            Message::OnlyInstancesHaveFields => "只有实例才有字段。".into(),
+            Message::NotCallable => "只能调用函数和类。".into(),
+            Message::ArityMismatch { expected, got } => {
+                format!("应有 {expected} 个实参，实际传入 {got} 个。")
+            }
+            Message::StackOverflow => "栈溢出。".into(),
+            Message::SuperclassMustBeClass => "父类必须是类。".into(),
+            Message::SuperOutsideMethod => "不能在方法外使用 'super'。".into(),
+            Message::UnknownBinaryOperator(op) => format!("未知的二元运算符 '{op}'。"),
+            Message::UnknownUnaryOperator(op) => format!("未知的一元运算符 '{op}'。"),
+            Message::Io(detail) => format!("I/O 错误：{detail}"),
+            Message::FileNotFound => "文件或目录不存在。".into(),
+            Message::PermissionDenied => "没有访问权限。".into(),
+            Message::InvalidUtf8(offset) => format!("字节偏移量 {offset} 处不是有效的 UTF-8。"),
+            Message::InvalidSyntaxTree(detail) => format!("无效的语法树：{detail}。"),
+        }
+    }
+}
+
+/// Text for a secondary label pointing at related code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Note {
+    OpeningParenthesis,
+    ParameterListOpened,
+    BlockOpened,
+    ClassBodyOpened,
+    ClassDeclared,
+    CantAssign,
+    InterpolationOpened,
+}
+
+impl Note {
+    pub fn text(self, lang: Lang) -> &'static str {
+        match (self, lang) {
+            (Note::OpeningParenthesis, Lang::En) => "opening parenthesis is here",
+            (Note::ParameterListOpened, Lang::En) => "parameter list opened here",
+            (Note::BlockOpened, Lang::En) => "block opened here",
+            (Note::ClassBodyOpened, Lang::En) => "class body opened here",
+            (Note::ClassDeclared, Lang::En) => "class declared here",
+            (Note::CantAssign, Lang::En) => "can't assign to this",
+            (Note::InterpolationOpened, Lang::En) => "interpolation starts here",
+            (Note::OpeningParenthesis, Lang::ZhCn) => "左括号在这里",
+            (Note::ParameterListOpened, Lang::ZhCn) => "参数列表从这里开始",
+            (Note::BlockOpened, Lang::ZhCn) => "代码块从这里开始",
+            (Note::ClassBodyOpened, Lang::ZhCn) => "类体从这里开始",
+            (Note::ClassDeclared, Lang::ZhCn) => "类在这里声明",
+            (Note::CantAssign, Lang::ZhCn) => "不能给它赋值",
+            (Note::InterpolationOpened, Lang::ZhCn) => "插值从这里开始",
+        }
+    }
+}
@@ -1,29 +1,71 @@
+use std::{cell::Cell, rc::Rc};
+
 use crate::{
     ast::{
-        BinaryExpr, Expr, ExpressionStmt, GroupingExpr, LiteralExpr, PrintStmt, Stmt, UnaryExpr,
-        VarStmt, VariableExpr,
+        AssignExpr, BinaryExpr, BlockStmt, CallExpr, ClassStmt, Expr, ExpressionStmt, FunctionStmt,
+        GetExpr, GroupingExpr, IfStmt, InterpolationExpr, LiteralExpr, LogicalExpr, PrintStmt,
+        ReturnStmt, SetExpr, Stmt, SuperExpr, ThisExpr, UnaryExpr, VarStmt, VariableExpr,
+        WhileStmt,
     },
     error::LoxError,
+    message::{FunctionKind, Message, Note},
+    span::Span,
     token::{Object, Token, TokenType},
 };
 
+const MAX_ARGUMENTS: usize = 255;
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<LoxError>,
 }
 
-/**
-*  expression → literal
-              | unary
-              | binary
-              | grouping ;
-
-   literal    → NUMBER | STRING | "true" | "false" | "nil" ;
-   grouping   → "(" expression ")" ;
-   unary      → ( "-" | "!" ) expression ;
-   binary     → expression operator expression ;
-   operator   → "==" | "!=" | "<" | "<=" | ">" | ">="
-              | "+"  | "-"  | "*" | "/" ;
+/*
+*  program     → declaration* EOF ;
+   declaration → classDecl
+               | funDecl
+               | varDecl
+               | statement ;
+   classDecl   → "class" IDENTIFIER ( "<" IDENTIFIER )?
+                 "{" function* "}" ;
+   funDecl     → "fun" function ;
+   function    → IDENTIFIER "(" parameters? ")" block ;
+   parameters  → IDENTIFIER ( "," IDENTIFIER )* ;
+   varDecl     → "var" IDENTIFIER ( "=" expression )? ";" ;
+   statement   → exprStmt
+               | forStmt
+               | ifStmt
+               | printStmt
+               | returnStmt
+               | whileStmt
+               | block ;
+   returnStmt  → "return" expression? ";" ;
+   forStmt     → "for" "(" ( varDecl | exprStmt | ";" )
+                 expression? ";"
+                 expression? ")" statement ;
+   ifStmt      → "if" "(" expression ")" statement
+                 ( "else" statement )? ;
+   whileStmt   → "while" "(" expression ")" statement ;
+   block       → "{" declaration* "}" ;
+
+   expression → assignment ;
+   assignment → ( call "." )? IDENTIFIER "=" assignment
+              | logic_or ;
+   logic_or   → logic_and ( "or" logic_and )* ;
+   logic_and  → equality ( "and" equality )* ;
+   equality   → comparison ( ( "!=" | "==" ) comparison )* ;
+   comparison → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+   term       → factor ( ( "-" | "+" ) factor )* ;
+   factor     → unary ( ( "/" | "*" ) unary )* ;
+   unary      → ( "!" | "-" ) unary
+              | call ;
+   call       → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+   arguments  → expression ( "," expression )* ;
+   primary    → NUMBER | STRING | "true" | "false" | "nil" | "this"
+              | "(" expression ")" | IDENTIFIER
+              | "super" "." IDENTIFIER | interpolation ;
+   interpolation → ( INTERPOLATION expression )+ STRING ;
 *
 */
 
@@ -32,67 +74,443 @@ impl Parser {
         Parser {
             tokens: vec![],
             current: 0,
+            errors: vec![],
         }
     }
 
-    pub fn parse(&mut self, tokens: Vec<Token>) -> Result<Vec<Stmt>, LoxError> {
+    /// Parses the whole token stream. Each syntax error is recorded and the
+    /// parser resynchronizes at the next statement, so every error in the
+    /// source is returned together.
+    pub fn parse(&mut self, tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<LoxError>> {
         self.tokens = tokens;
         self.current = 0;
+        self.errors = vec![];
         let mut statements = vec![];
         while !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
         }
-        Ok(statements)
     }
 
-    fn declaration(&mut self) -> Result<Stmt, LoxError> {
-        if self.match_token(TokenType::VAR) {
-            self.var_declaration()
+    /// Parses the token stream as one expression with nothing after it, as
+    /// the REPL accepts a bare expression in place of a statement.
+    pub fn parse_expression(&mut self, tokens: Vec<Token>) -> Result<Expr, LoxError> {
+        self.tokens = tokens;
+        self.current = 0;
+        self.errors = vec![];
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            return Err(self.error(self.peek(), Message::ExpectSemicolonAfterValue));
+        }
+        Ok(expr)
+    }
+
+    fn declaration(&mut self) -> Option<Stmt> {
+        let doc = self.peek().doc.clone();
+        let result = if self.match_token(TokenType::CLASS) {
+            self.class_declaration(doc)
+        } else if self.match_token(TokenType::FUN) {
+            let start = self.previous().span;
+            self.function(FunctionKind::Function, doc)
+                .map(|mut function| {
+                    function.span = start.to(function.span);
+                    Stmt::FunctionStmt(function)
+                })
+        } else if self.match_token(TokenType::VAR) {
+            self.var_declaration(doc)
         } else {
             self.statement()
+        };
+
+        match result {
+            Ok(stmt) => Some(stmt),
+            Err(err) => {
+                self.errors.push(err);
+                self.synchronize();
+                None
+            }
         }
     }
 
-    fn var_declaration(&mut self) -> Result<Stmt, LoxError> {
-        let name = self.consume(TokenType::IDENTIFIER, String::from("Expect variable name."))?;
+    /// `doc` is the text of the `///` comments before the declaration, here
+    /// and in `function` and `var_declaration`.
+    fn class_declaration(&mut self, doc: Option<String>) -> Result<Stmt, LoxError> {
+        let start = self.previous().span;
+        let name = self.consume(TokenType::IDENTIFIER, Message::ExpectClassName)?;
+
+        let mut superclass = None;
+        if self.match_token(TokenType::LESS) {
+            let superclass_name =
+                self.consume(TokenType::IDENTIFIER, Message::ExpectSuperclassName)?;
+            if superclass_name.lexeme == name.lexeme {
+                let err = self
+                    .error(&superclass_name, Message::InheritFromSelf)
+                    .with_label(name.span, Note::ClassDeclared);
+                self.errors.push(err);
+            }
+            superclass = Some(VariableExpr {
+                span: superclass_name.span,
+                name: superclass_name,
+                depth: Cell::new(None),
+            });
+        }
+
+        let brace = self.consume(
+            TokenType::LEFT_BRACE,
+            Message::ExpectLeftBraceBeforeClassBody,
+        )?;
+
+        let mut methods = vec![];
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            let doc = self.peek().doc.clone();
+            methods.push(self.function(FunctionKind::Method, doc)?);
+        }
+
+        self.consume_closing(
+            TokenType::RIGHT_BRACE,
+            Message::ExpectRightBraceAfterClassBody,
+            brace.span,
+            Note::ClassBodyOpened,
+        )?;
+        Ok(Stmt::ClassStmt(ClassStmt {
+            name,
+            superclass,
+            methods,
+            doc,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn function(
+        &mut self,
+        kind: FunctionKind,
+        doc: Option<String>,
+    ) -> Result<FunctionStmt, LoxError> {
+        let name = self.consume(TokenType::IDENTIFIER, Message::ExpectFunctionName(kind))?;
+        let paren = self.consume(
+            TokenType::LEFT_PAREN,
+            Message::ExpectLeftParenAfterFunctionName(kind),
+        )?;
+        let mut params = vec![];
+        if !self.check(TokenType::RIGHT_PAREN) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    let err = self.error(self.peek(), Message::TooManyParameters(MAX_ARGUMENTS));
+                    self.errors.push(err);
+                }
+                params.push(self.consume(TokenType::IDENTIFIER, Message::ExpectParameterName)?);
+                if !self.match_token(TokenType::COMMA) {
+                    break;
+                }
+            }
+        }
+        self.consume_closing(
+            TokenType::RIGHT_PAREN,
+            Message::ExpectRightParenAfterParameters,
+            paren.span,
+            Note::ParameterListOpened,
+        )?;
+
+        self.consume(
+            TokenType::LEFT_BRACE,
+            Message::ExpectLeftBraceBeforeFunctionBody(kind),
+        )?;
+        let body = self.block()?;
+        Ok(FunctionStmt {
+            span: self.span_from(name.span),
+            name,
+            params,
+            body: Rc::new(body),
+            doc,
+        })
+    }
+
+    fn var_declaration(&mut self, doc: Option<String>) -> Result<Stmt, LoxError> {
+        let start = self.previous().span;
+        let name = self.consume(TokenType::IDENTIFIER, Message::ExpectVariableName)?;
         let mut initializer = None;
         if self.match_token(TokenType::EQUAL) {
             initializer = Some(self.expression()?);
         }
         self.consume(
             TokenType::SEMICOLON,
-            String::from("Expect ';' after variable declaration."),
+            Message::ExpectSemicolonAfterVariableDeclaration,
         )?;
-        Ok(Stmt::VarStmt(VarStmt { name, initializer }))
+        Ok(Stmt::VarStmt(VarStmt {
+            name,
+            initializer,
+            doc,
+            span: self.span_from(start),
+        }))
     }
 
     fn statement(&mut self) -> Result<Stmt, LoxError> {
+        if self.match_token(TokenType::FOR) {
+            return self.for_statement();
+        }
+        if self.match_token(TokenType::IF) {
+            return self.if_statement();
+        }
         if self.match_token(TokenType::PRINT) {
             return self.print_statement();
         }
+        if self.match_token(TokenType::RETURN) {
+            return self.return_statement();
+        }
+        if self.match_token(TokenType::WHILE) {
+            return self.while_statement();
+        }
+        if self.match_token(TokenType::LEFT_BRACE) {
+            let start = self.previous().span;
+            return Ok(Stmt::BlockStmt(BlockStmt {
+                statements: self.block()?,
+                span: self.span_from(start),
+            }));
+        }
         self.expression_statement()
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, LoxError> {
-        let value = self.expression()?;
+    fn for_statement(&mut self) -> Result<Stmt, LoxError> {
+        let start = self.previous().span;
+        self.consume(TokenType::LEFT_PAREN, Message::ExpectLeftParenAfter("for"))?;
+
+        let initializer = if self.match_token(TokenType::SEMICOLON) {
+            None
+        } else if self.match_token(TokenType::VAR) {
+            Some(self.var_declaration(None)?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(TokenType::SEMICOLON) {
+            Expr::Literal(LiteralExpr {
+                value: Object::Bool(true),
+                span: self.peek().span,
+            })
+        } else {
+            self.expression()?
+        };
         self.consume(
             TokenType::SEMICOLON,
-            String::from("Expect ';' after value."),
+            Message::ExpectSemicolonAfterLoopCondition,
         )?;
-        Ok(Stmt::PrintStmt(PrintStmt { expression: value }))
+
+        let increment = if self.check(TokenType::RIGHT_PAREN) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(
+            TokenType::RIGHT_PAREN,
+            Message::ExpectRightParenAfterForClauses,
+        )?;
+
+        // Desugar `for` into a `while` loop wrapped in the blocks it needs.
+        let mut body = self.statement()?;
+        // The synthesized nodes all cover the whole `for` statement.
+        let span = self.span_from(start);
+        if let Some(increment) = increment {
+            body = Stmt::BlockStmt(BlockStmt {
+                statements: vec![
+                    body,
+                    Stmt::ExpressionStmt(ExpressionStmt {
+                        span: increment.span(),
+                        expression: increment,
+                    }),
+                ],
+                span,
+            });
+        }
+        body = Stmt::WhileStmt(WhileStmt {
+            condition,
+            body: Box::new(body),
+            span,
+        });
+        if let Some(initializer) = initializer {
+            body = Stmt::BlockStmt(BlockStmt {
+                statements: vec![initializer, body],
+                span,
+            });
+        }
+        Ok(body)
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt, LoxError> {
-        let value = self.expression()?;
+    fn if_statement(&mut self) -> Result<Stmt, LoxError> {
+        let start = self.previous().span;
+        self.consume(TokenType::LEFT_PAREN, Message::ExpectLeftParenAfter("if"))?;
+        let condition = self.expression()?;
+        self.consume(
+            TokenType::RIGHT_PAREN,
+            Message::ExpectRightParenAfterIfCondition,
+        )?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_token(TokenType::ELSE) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Stmt::IfStmt(IfStmt {
+            condition,
+            then_branch,
+            else_branch,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        let value = if self.check(TokenType::SEMICOLON) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
         self.consume(
             TokenType::SEMICOLON,
-            String::from("Expect ';' after value."),
+            Message::ExpectSemicolonAfterReturnValue,
+        )?;
+        Ok(Stmt::ReturnStmt(ReturnStmt {
+            span: self.span_from(keyword.span),
+            keyword,
+            value,
+        }))
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, LoxError> {
+        let start = self.previous().span;
+        self.consume(
+            TokenType::LEFT_PAREN,
+            Message::ExpectLeftParenAfter("while"),
+        )?;
+        let condition = self.expression()?;
+        self.consume(
+            TokenType::RIGHT_PAREN,
+            Message::ExpectRightParenAfterCondition,
         )?;
-        Ok(Stmt::ExpressionStmt(ExpressionStmt { expression: value }))
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::WhileStmt(WhileStmt {
+            condition,
+            body,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, LoxError> {
+        let brace = self.previous().span;
+        let mut statements = vec![];
+
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
+        }
+
+        self.consume_closing(
+            TokenType::RIGHT_BRACE,
+            Message::ExpectRightBraceAfterBlock,
+            brace,
+            Note::BlockOpened,
+        )?;
+        Ok(statements)
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, LoxError> {
+        let start = self.previous().span;
+        let value = self.expression()?;
+        self.consume(TokenType::SEMICOLON, Message::ExpectSemicolonAfterValue)?;
+        Ok(Stmt::PrintStmt(PrintStmt {
+            expression: value,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, LoxError> {
+        let value = self.expression()?;
+        self.consume(TokenType::SEMICOLON, Message::ExpectSemicolonAfterValue)?;
+        Ok(Stmt::ExpressionStmt(ExpressionStmt {
+            span: self.span_from(value.span()),
+            expression: value,
+        }))
     }
 
     fn expression(&mut self) -> Result<Expr, LoxError> {
-        self.equality()
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr, LoxError> {
+        let expr = self.or()?;
+
+        if self.match_token(TokenType::EQUAL) {
+            let equals = self.previous().clone();
+            let value = self.assignment()?;
+
+            let target = expr.span();
+            let span = target.to(value.span());
+            return match expr {
+                Expr::Variable(var) => Ok(Expr::Assign(AssignExpr {
+                    name: var.name,
+                    value: Box::new(value),
+                    depth: Cell::new(None),
+                    span,
+                })),
+                Expr::Get(get) => Ok(Expr::Set(SetExpr {
+                    object: get.object,
+                    name: get.name,
+                    value: Box::new(value),
+                    span,
+                })),
+                _ => {
+                    // The parser isn't confused here, so report without unwinding.
+                    let err = self
+                        .error(&equals, Message::InvalidAssignmentTarget)
+                        .with_label(target, Note::CantAssign);
+                    self.errors.push(err);
+                    Ok(value)
+                }
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, LoxError> {
+        let mut expr = self.and()?;
+
+        while self.match_token(TokenType::OR) {
+            let operator = self.previous().clone();
+            let right = self.and()?;
+            expr = Expr::Logical(LogicalExpr {
+                span: expr.span().to(right.span()),
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            })
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, LoxError> {
+        let mut expr = self.equality()?;
+
+        while self.match_token(TokenType::AND) {
+            let operator = self.previous().clone();
+            let right = self.equality()?;
+            expr = Expr::Logical(LogicalExpr {
+                span: expr.span().to(right.span()),
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            })
+        }
+
+        Ok(expr)
     }
 
     fn equality(&mut self) -> Result<Expr, LoxError> {
@@ -102,6 +520,7 @@ impl Parser {
             let operator = self.previous().clone();
             let right = self.comparison()?;
             expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -122,6 +541,7 @@ impl Parser {
             let operator = self.previous().clone();
             let right = self.term()?;
             expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 right: Box::new(right),
                 operator,
@@ -137,6 +557,7 @@ impl Parser {
             let operator = self.previous().clone();
             let right = self.factor()?;
             let bin_expr = BinaryExpr {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 right: Box::new(right),
                 operator,
@@ -153,6 +574,7 @@ impl Parser {
             let operator = self.previous().clone();
             let right = self.unary()?;
             expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 right: Box::new(right),
                 operator,
@@ -166,60 +588,193 @@ impl Parser {
             let operator = self.previous().clone();
             let right = self.unary()?;
             return Ok(Expr::Unary(UnaryExpr {
+                span: operator.span.to(right.span()),
                 operator,
                 right: Box::new(right),
             }));
         }
-        self.primary()
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, LoxError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_token(TokenType::LEFT_PAREN) {
+                let paren = self.previous().span;
+                expr = self.finish_call(expr, paren)?;
+            } else if self.match_token(TokenType::DOT) {
+                let name = self.consume(TokenType::IDENTIFIER, Message::ExpectPropertyName)?;
+                expr = Expr::Get(GetExpr {
+                    span: expr.span().to(name.span),
+                    object: Box::new(expr),
+                    name,
+                });
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr, open_paren: Span) -> Result<Expr, LoxError> {
+        let mut arguments = vec![];
+        if !self.check(TokenType::RIGHT_PAREN) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    let err = self.error(self.peek(), Message::TooManyArguments(MAX_ARGUMENTS));
+                    self.errors.push(err);
+                }
+                arguments.push(self.expression()?);
+                if !self.match_token(TokenType::COMMA) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume_closing(
+            TokenType::RIGHT_PAREN,
+            Message::ExpectRightParenAfterArguments,
+            open_paren,
+            Note::OpeningParenthesis,
+        )?;
+        Ok(Expr::Call(CallExpr {
+            span: callee.span().to(paren.span),
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        }))
     }
 
     fn primary(&mut self) -> Result<Expr, LoxError> {
         if self.match_token(TokenType::FALSE) {
             return Ok(Expr::Literal(LiteralExpr {
-                value: Object::False,
+                value: Object::Bool(false),
+                span: self.previous().span,
             }));
         }
 
         if self.match_token(TokenType::TRUE) {
             return Ok(Expr::Literal(LiteralExpr {
-                value: Object::True,
+                value: Object::Bool(true),
+                span: self.previous().span,
+            }));
+        }
+
+        if self.match_token(TokenType::SUPER) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::DOT, Message::ExpectDotAfterSuper)?;
+            let method =
+                self.consume(TokenType::IDENTIFIER, Message::ExpectSuperclassMethodName)?;
+            return Ok(Expr::Super(SuperExpr {
+                span: keyword.span.to(method.span),
+                keyword,
+                method,
+                depth: Cell::new(None),
+            }));
+        }
+
+        if self.match_token(TokenType::THIS) {
+            return Ok(Expr::This(ThisExpr {
+                keyword: self.previous().clone(),
+                depth: Cell::new(None),
+                span: self.previous().span,
             }));
         }
 
         if self.match_token(TokenType::IDENTIFIER) {
             return Ok(Expr::Variable(VariableExpr {
                 name: self.previous().clone(),
+                depth: Cell::new(None),
+                span: self.previous().span,
             }));
         }
 
         if self.match_token(TokenType::NIL) {
-            return Ok(Expr::Literal(LiteralExpr { value: Object::Nil }));
+            return Ok(Expr::Literal(LiteralExpr {
+                value: Object::Nil,
+                span: self.previous().span,
+            }));
         }
 
         if self.match_token(TokenType::STRING) || self.match_token(TokenType::NUMBER) {
             let value = self.previous().literal.clone();
             return Ok(Expr::Literal(LiteralExpr {
                 value: value.unwrap(),
+                span: self.previous().span,
             }));
         }
 
+        if self.match_token(TokenType::INTERPOLATION) {
+            return self.interpolation();
+        }
+
         if self.match_token(TokenType::LEFT_PAREN) {
+            let start = self.previous().span;
             let expr = self.expression()?;
-            self.consume(
+            self.consume_closing(
                 TokenType::RIGHT_PAREN,
-                "Expect ')' after expression.".to_string(),
+                Message::ExpectRightParenAfterExpression,
+                start,
+                Note::OpeningParenthesis,
             )?;
             return Ok(Expr::Grouping(GroupingExpr {
                 expression: Box::new(expr),
+                span: self.span_from(start),
             }));
         }
 
-        Err(self.error(
-            self.peek().clone(),
-            String::from("Unrecongnized token during parsed."),
-        ))
+        Err(self.error(self.peek(), Message::ExpectExpression))
+    }
+
+    /// Parses the rest of a string with `${...}` in it, after its first
+    /// INTERPOLATION token. Empty text between interpolations is left out.
+    fn interpolation(&mut self) -> Result<Expr, LoxError> {
+        let start = self.previous().span;
+        let mut parts = vec![];
+        loop {
+            let text = self.previous().clone();
+            if let Some(Object::Str(value)) = text.literal {
+                if !value.is_empty() {
+                    parts.push(Expr::Literal(LiteralExpr {
+                        value: Object::Str(value),
+                        span: text.span,
+                    }));
+                }
+            }
+            if text.tty == TokenType::STRING {
+                break;
+            }
+            // The rest of the string straight after `${` means `${}`.
+            let next = self.peek();
+            if matches!(next.tty, TokenType::STRING | TokenType::INTERPOLATION)
+                && next.lexeme.starts_with('}')
+            {
+                return Err(self.error(next, Message::ExpectExpression));
+            }
+            parts.push(self.expression()?);
+            if !self.match_token(TokenType::INTERPOLATION) {
+                // The token ends with the `${` that opened the expression.
+                let opening = Span {
+                    start: text.span.end - 2,
+                    ..text.span
+                };
+                self.consume_closing(
+                    TokenType::STRING,
+                    Message::ExpectRightBraceAfterInterpolation,
+                    opening,
+                    Note::InterpolationOpened,
+                )?;
+            }
+        }
+        Ok(Expr::Interpolation(InterpolationExpr {
+            parts,
+            span: self.span_from(start),
+        }))
     }
 
+    /// Discards tokens until the start of the next statement, so one syntax
+    /// error doesn't cascade into a string of bogus ones.
     fn synchronize(&mut self) {
         self.advance();
 
@@ -236,29 +791,47 @@ impl Parser {
                 | TokenType::IF
                 | TokenType::WHILE
                 | TokenType::PRINT
-                | TokenType::RETURN => {}
+                | TokenType::RETURN => return,
                 _ => {}
             }
             self.advance();
         }
     }
 
-    fn consume(&mut self, tty: TokenType, message: String) -> Result<Token, LoxError> {
+    /// Span from `start` through the most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+
+    fn consume(&mut self, tty: TokenType, message: Message) -> Result<Token, LoxError> {
         if self.check(tty) {
             return Ok(self.advance());
         }
-        Err(self.error(self.peek().clone(), message))
+        Err(self.error(self.peek(), message))
+    }
+
+    /// Like `consume`, but on failure also points at the token that opened
+    /// the construct being closed.
+    fn consume_closing(
+        &mut self,
+        tty: TokenType,
+        message: Message,
+        opening: Span,
+        label: Note,
+    ) -> Result<Token, LoxError> {
+        self.consume(tty, message)
+            .map_err(|err| err.with_label(opening, label))
     }
 
-    fn error(&self, token: Token, message: String) -> LoxError {
-        LoxError::new_compile(message, token.line)
+    fn error(&self, token: &Token, message: Message) -> LoxError {
+        LoxError::new_parse(token, message)
     }
 
     fn advance(&mut self) -> Token {
         if !self.is_at_end() {
             self.current += 1;
         }
-        return self.previous().clone();
+        self.previous().clone()
     }
 
     fn check(&self, tty: TokenType) -> bool {
@@ -281,7 +854,7 @@ impl Parser {
             self.advance();
             return true;
         }
-        return false;
+        false
     }
 
     fn previous(&self) -> &Token {
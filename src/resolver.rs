@@ -0,0 +1,348 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+
+use crate::{ast::*, error::LoxError, message::Message, token::Token};
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+/// Static pass run between parsing and interpreting. It records on each
+/// variable reference how many scopes away its declaration lives, and rejects
+/// programs that misuse scopes before any of their code runs.
+pub struct Resolver {
+    /// Innermost scope last. The flag tells whether the variable's initializer
+    /// has finished, so a local can't be read while it is being defined.
+    scopes: RefCell<Vec<HashMap<String, bool>>>,
+    current_function: Cell<FunctionType>,
+    current_class: Cell<ClassType>,
+    /// Every error found so far. Resolving carries on past each one, so a
+    /// single pass reports them all, as the parser does.
+    errors: RefCell<Vec<LoxError>>,
+}
+
+impl ExprVisitor<()> for Resolver {
+    fn visit_assign_expr(&self, expr: &AssignExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&expr.value)?;
+        self.resolve_local(&expr.depth, &expr.name);
+        Ok(())
+    }
+
+    fn visit_binary_expr(&self, expr: &BinaryExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&expr.left)?;
+        self.resolve_expr(&expr.right)
+    }
+
+    fn visit_call_expr(&self, expr: &CallExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&expr.callee)?;
+        for argument in &expr.arguments {
+            self.resolve_expr(argument)?;
+        }
+        Ok(())
+    }
+
+    fn visit_get_expr(&self, expr: &GetExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&expr.object)
+    }
+
+    fn visit_grouping_expr(&self, expr: &GroupingExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&expr.expression)
+    }
+
+    fn visit_interpolation_expr(&self, expr: &InterpolationExpr) -> Result<(), LoxError> {
+        for part in &expr.parts {
+            self.resolve_expr(part)?;
+        }
+        Ok(())
+    }
+
+    fn visit_literal_expr(&self, _: &LiteralExpr) -> Result<(), LoxError> {
+        Ok(())
+    }
+
+    fn visit_logical_expr(&self, expr: &LogicalExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&expr.left)?;
+        self.resolve_expr(&expr.right)
+    }
+
+    fn visit_set_expr(&self, expr: &SetExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&expr.value)?;
+        self.resolve_expr(&expr.object)
+    }
+
+    fn visit_super_expr(&self, expr: &SuperExpr) -> Result<(), LoxError> {
+        match self.current_class.get() {
+            ClassType::None => self.error(&expr.keyword, Message::SuperOutsideClass),
+            ClassType::Class => self.error(&expr.keyword, Message::SuperWithoutSuperclass),
+            ClassType::Subclass => self.resolve_local(&expr.depth, &expr.keyword),
+        }
+        Ok(())
+    }
+
+    fn visit_this_expr(&self, expr: &ThisExpr) -> Result<(), LoxError> {
+        if self.current_class.get() == ClassType::None {
+            self.error(&expr.keyword, Message::ThisOutsideClass);
+        }
+        self.resolve_local(&expr.depth, &expr.keyword);
+        Ok(())
+    }
+
+    fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&expr.right)
+    }
+
+    fn visit_variable_expr(&self, expr: &VariableExpr) -> Result<(), LoxError> {
+        let in_own_initializer = self
+            .scopes
+            .borrow()
+            .last()
+            .and_then(|scope| scope.get(&expr.name.lexeme))
+            == Some(&false);
+        if in_own_initializer {
+            self.error(&expr.name, Message::ReadInOwnInitializer);
+        }
+        self.resolve_local(&expr.depth, &expr.name);
+        Ok(())
+    }
+}
+
+impl StmtVisitor<()> for Resolver {
+    fn visit_block_stmt(&self, stmt: &BlockStmt) -> Result<(), LoxError> {
+        self.begin_scope();
+        let result = self.resolve_stmts(&stmt.statements);
+        self.end_scope();
+        result
+    }
+
+    fn visit_class_stmt(&self, stmt: &ClassStmt) -> Result<(), LoxError> {
+        let enclosing_class = self.current_class.replace(ClassType::Class);
+        let result = self.resolve_class(stmt);
+        self.current_class.set(enclosing_class);
+        result
+    }
+
+    fn visit_expression_stmt(&self, stmt: &ExpressionStmt) -> Result<(), LoxError> {
+        self.resolve_expr(&stmt.expression)
+    }
+
+    fn visit_function_stmt(&self, stmt: &FunctionStmt) -> Result<(), LoxError> {
+        self.declare(&stmt.name);
+        self.define(&stmt.name);
+        self.resolve_function(stmt, FunctionType::Function)
+    }
+
+    fn visit_if_stmt(&self, stmt: &IfStmt) -> Result<(), LoxError> {
+        self.resolve_expr(&stmt.condition)?;
+        self.resolve_stmt(&stmt.then_branch)?;
+        if let Some(else_branch) = &stmt.else_branch {
+            self.resolve_stmt(else_branch)?;
+        }
+        Ok(())
+    }
+
+    fn visit_print_stmt(&self, stmt: &PrintStmt) -> Result<(), LoxError> {
+        self.resolve_expr(&stmt.expression)
+    }
+
+    fn visit_return_stmt(&self, stmt: &ReturnStmt) -> Result<(), LoxError> {
+        if self.current_function.get() == FunctionType::None {
+            self.error(&stmt.keyword, Message::TopLevelReturn);
+        }
+        if let Some(value) = &stmt.value {
+            if self.current_function.get() == FunctionType::Initializer {
+                self.error(&stmt.keyword, Message::ReturnFromInitializer);
+            }
+            self.resolve_expr(value)?;
+        }
+        Ok(())
+    }
+
+    fn visit_var_stmt(&self, stmt: &VarStmt) -> Result<(), LoxError> {
+        self.declare(&stmt.name);
+        if let Some(initializer) = &stmt.initializer {
+            self.resolve_expr(initializer)?;
+        }
+        self.define(&stmt.name);
+        Ok(())
+    }
+
+    fn visit_while_stmt(&self, stmt: &WhileStmt) -> Result<(), LoxError> {
+        self.resolve_expr(&stmt.condition)?;
+        self.resolve_stmt(&stmt.body)
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: RefCell::new(vec![]),
+            current_function: Cell::new(FunctionType::None),
+            current_class: Cell::new(ClassType::None),
+            errors: RefCell::new(vec![]),
+        }
+    }
+
+    /// Resolves a whole program, returning every error in it.
+    pub fn resolve(&self, statements: &[Stmt]) -> Result<(), Vec<LoxError>> {
+        self.resolve_stmts(statements).map_err(|err| vec![err])?;
+        self.take_errors()
+    }
+
+    pub fn resolve_expression(&self, expr: &Expr) -> Result<(), Vec<LoxError>> {
+        self.resolve_expr(expr).map_err(|err| vec![err])?;
+        self.take_errors()
+    }
+
+    fn take_errors(&self) -> Result<(), Vec<LoxError>> {
+        let errors = self.errors.take();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn resolve_stmts(&self, statements: &[Stmt]) -> Result<(), LoxError> {
+        for statement in statements {
+            self.resolve_stmt(statement)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_stmt(&self, stmt: &Stmt) -> Result<(), LoxError> {
+        match stmt {
+            Stmt::BlockStmt(n) => n.accept(self),
+            Stmt::ClassStmt(n) => n.accept(self),
+            Stmt::ExpressionStmt(n) => n.accept(self),
+            Stmt::FunctionStmt(n) => n.accept(self),
+            Stmt::IfStmt(n) => n.accept(self),
+            Stmt::PrintStmt(n) => n.accept(self),
+            Stmt::ReturnStmt(n) => n.accept(self),
+            Stmt::VarStmt(n) => n.accept(self),
+            Stmt::WhileStmt(n) => n.accept(self),
+        }
+    }
+
+    fn resolve_expr(&self, expr: &Expr) -> Result<(), LoxError> {
+        match expr {
+            Expr::Assign(n) => n.accept(self),
+            Expr::Binary(n) => n.accept(self),
+            Expr::Call(n) => n.accept(self),
+            Expr::Get(n) => n.accept(self),
+            Expr::Grouping(n) => n.accept(self),
+            Expr::Interpolation(n) => n.accept(self),
+            Expr::Literal(n) => n.accept(self),
+            Expr::Logical(n) => n.accept(self),
+            Expr::Set(n) => n.accept(self),
+            Expr::Super(n) => n.accept(self),
+            Expr::This(n) => n.accept(self),
+            Expr::Unary(n) => n.accept(self),
+            Expr::Variable(n) => n.accept(self),
+        }
+    }
+
+    fn resolve_class(&self, stmt: &ClassStmt) -> Result<(), LoxError> {
+        self.declare(&stmt.name);
+        self.define(&stmt.name);
+
+        if let Some(superclass) = &stmt.superclass {
+            self.current_class.set(ClassType::Subclass);
+            self.visit_variable_expr(superclass)?;
+            self.begin_scope();
+            self.define_name("super");
+        }
+
+        self.begin_scope();
+        self.define_name("this");
+        let result = stmt.methods.iter().try_for_each(|method| {
+            let kind = if method.name.lexeme == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(method, kind)
+        });
+        self.end_scope();
+
+        if stmt.superclass.is_some() {
+            self.end_scope();
+        }
+        result
+    }
+
+    fn resolve_function(
+        &self,
+        function: &FunctionStmt,
+        kind: FunctionType,
+    ) -> Result<(), LoxError> {
+        let enclosing_function = self.current_function.replace(kind);
+        self.begin_scope();
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        let result = self.resolve_stmts(&function.body);
+        self.end_scope();
+        self.current_function.set(enclosing_function);
+        result
+    }
+
+    /// Records how many scopes out `name` is declared. Names not found in any
+    /// scope are left unresolved and treated as globals by the interpreter.
+    fn resolve_local(&self, depth: &Cell<Option<usize>>, name: &Token) {
+        let scopes = self.scopes.borrow();
+        let found = scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme));
+        depth.set(found);
+    }
+
+    fn begin_scope(&self) {
+        self.scopes.borrow_mut().push(HashMap::new());
+    }
+
+    fn end_scope(&self) {
+        self.scopes.borrow_mut().pop();
+    }
+
+    fn declare(&self, name: &Token) {
+        let mut scopes = self.scopes.borrow_mut();
+        let Some(scope) = scopes.last_mut() else {
+            return;
+        };
+        if scope.contains_key(&name.lexeme) {
+            self.error(name, Message::AlreadyDeclared);
+        }
+        scope.insert(name.lexeme.clone(), false);
+    }
+
+    fn define(&self, name: &Token) {
+        self.define_name(&name.lexeme);
+    }
+
+    fn define_name(&self, name: &str) {
+        if let Some(scope) = self.scopes.borrow_mut().last_mut() {
+            scope.insert(name.to_string(), true);
+        }
+    }
+
+    fn error(&self, token: &Token, message: Message) {
+        self.errors
+            .borrow_mut()
+            .push(LoxError::new_resolve(token, message));
+    }
+}
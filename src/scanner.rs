@@ -1,10 +1,13 @@
 use crate::{
     error::LoxError,
     hashmap,
+    message::Message,
+    span::Span,
     token::{Object, Token, TokenType},
 };
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
+use unicode_xid::UnicodeXID;
 
 lazy_static! {
     static ref KEY_WORDS: HashMap<String, TokenType> = {
@@ -29,12 +32,46 @@ lazy_static! {
     };
 }
 
+/// A string literal being scanned. Its text may be split by `${...}`
+/// interpolations, whose expressions are scanned as ordinary tokens.
+struct StringLiteral {
+    raw: bool,
+    triple: bool,
+    /// '{'s opened in the current interpolation and not closed yet, so their
+    /// '}'s aren't taken for the end of the interpolation.
+    depth: usize,
+    /// Character ranges of the text before, between and after
+    /// interpolations.
+    segments: Vec<Range<usize>>,
+    /// The INTERPOLATION token added at each `${`. Their values are filled
+    /// in once the whole string has been scanned.
+    tokens: Vec<usize>,
+}
+
 pub struct Scanner {
     source: Vec<char>,
     tokens: Vec<Token>,
+    errors: Vec<LoxError>,
     start: usize,
     current: usize,
     line: usize,
+    /// Index of the first character on the current line.
+    line_start: usize,
+    /// Line and column where the token being scanned began.
+    start_line: usize,
+    start_column: usize,
+    /// Byte offsets into the source of `start` and `current`, which index
+    /// characters rather than bytes.
+    start_offset: usize,
+    offset: usize,
+    /// Strings whose interpolated expressions are being scanned, innermost
+    /// last.
+    interpolations: Vec<StringLiteral>,
+    /// Number `Lox` gave the source, recorded in every span.
+    source_id: usize,
+    /// Text of the `///` comments since the last token, one line each. It
+    /// goes to the next token scanned.
+    doc: Vec<String>,
 }
 
 impl Scanner {
@@ -42,97 +79,190 @@ impl Scanner {
         Self {
             source: vec![],
             tokens: vec![],
+            errors: vec![],
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            start_offset: 0,
+            offset: 0,
+            interpolations: vec![],
+            source_id: 0,
+            doc: vec![],
         }
     }
 
-    fn init(&mut self, source: String) {
+    fn init(&mut self, source: String, source_id: usize) {
         self.source = source.chars().collect();
+        self.source_id = source_id;
         self.tokens = vec![];
+        self.errors = vec![];
         self.start = 0;
         self.current = 0;
         self.line = 1;
+        self.line_start = 0;
+        self.start_line = 1;
+        self.start_column = 1;
+        self.start_offset = 0;
+        self.offset = 0;
+        self.interpolations = vec![];
+        self.doc = vec![];
     }
 
-    pub fn scan_tokens(&mut self, source: String) -> Result<&Vec<Token>, LoxError> {
-        self.init(source);
+    /// Scans the whole source, skipping over anything it can't lex. Returns
+    /// the tokens it produced together with every error it ran into, in
+    /// source order. Their spans are marked as belonging to `source_id`.
+    pub fn scan_tokens(&mut self, source: String, source_id: usize) -> (Vec<Token>, Vec<LoxError>) {
+        self.init(source, source_id);
 
         while !self.is_at_end() {
             self.start = self.current;
-            let c = self.advance();
-            match c {
-                '(' => self.add_token(TokenType::LEFT_PAREN),
-                ')' => self.add_token(TokenType::RIGHT_PAREN),
-                '{' => self.add_token(TokenType::LEFT_BRACE),
-                '}' => self.add_token(TokenType::RIGHT_BRACE),
-                ',' => self.add_token(TokenType::COMMA),
-                '.' => self.add_token(TokenType::DOT),
-                '-' => self.add_token(TokenType::MINUS),
-                '+' => self.add_token(TokenType::PLUS),
-                ';' => self.add_token(TokenType::SEMICOLON),
-                '*' => self.add_token(TokenType::STAR),
-                '!' => {
-                    let tty = if self.match_char('=') {
-                        TokenType::BANG_EQUAL
-                    } else {
-                        TokenType::BANG
-                    };
-                    self.add_token(tty)
-                }
-                '=' => {
-                    let tty = if self.match_char('=') {
-                        TokenType::EQUAL_EQUAL
-                    } else {
-                        TokenType::EQUAL
-                    };
-                    self.add_token(tty)
+            self.start_line = self.line;
+            self.start_column = self.column(self.start);
+            self.start_offset = self.offset;
+            if let Err(err) = self.scan_token() {
+                self.errors.push(err);
+            }
+        }
+        self.tokens.push(Token::eof(
+            self.line,
+            self.column(self.current),
+            self.span(self.offset, self.offset),
+        ));
+        // Escapes are only checked once a string is complete, after any
+        // errors inside its interpolations were found.
+        self.errors
+            .sort_by_key(|err| err.span().map(|span| span.start));
+        (
+            std::mem::take(&mut self.tokens),
+            std::mem::take(&mut self.errors),
+        )
+    }
+
+    fn scan_token(&mut self) -> Result<(), LoxError> {
+        let c = self.advance();
+        match c {
+            '(' => self.add_token(TokenType::LEFT_PAREN),
+            ')' => self.add_token(TokenType::RIGHT_PAREN),
+            '{' => {
+                if let Some(string) = self.interpolations.last_mut() {
+                    string.depth += 1;
                 }
-                '<' => {
-                    let tty = if self.match_char('=') {
-                        TokenType::LESS_EQUAL
-                    } else {
-                        TokenType::LESS
-                    };
-                    self.add_token(tty)
+                self.add_token(TokenType::LEFT_BRACE)
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(string) if string.depth == 0 => {
+                    let string = self.interpolations.pop().unwrap();
+                    self.string_segment(string)?
                 }
-                '>' => {
-                    let tty = if self.match_char('=') {
-                        TokenType::GREATER_EQUAL
-                    } else {
-                        TokenType::GREATER
-                    };
-                    self.add_token(tty)
+                Some(string) => {
+                    string.depth -= 1;
+                    self.add_token(TokenType::RIGHT_BRACE)
                 }
-                '/' => {
-                    if self.match_char('/') {
-                        while self.peek() != '\n' && !self.is_at_end() {
-                            self.advance();
-                        }
-                    } else {
-                        self.add_token(TokenType::SLASH)
+                None => self.add_token(TokenType::RIGHT_BRACE),
+            },
+            ',' => self.add_token(TokenType::COMMA),
+            '.' if self.is_digit(self.peek()) && !self.after_operand() => self.number()?,
+            '.' => self.add_token(TokenType::DOT),
+            '-' => self.add_token(TokenType::MINUS),
+            '+' => self.add_token(TokenType::PLUS),
+            ';' => self.add_token(TokenType::SEMICOLON),
+            '*' => self.add_token(TokenType::STAR),
+            '!' => {
+                let tty = if self.match_char('=') {
+                    TokenType::BANG_EQUAL
+                } else {
+                    TokenType::BANG
+                };
+                self.add_token(tty)
+            }
+            '=' => {
+                let tty = if self.match_char('=') {
+                    TokenType::EQUAL_EQUAL
+                } else {
+                    TokenType::EQUAL
+                };
+                self.add_token(tty)
+            }
+            '<' => {
+                let tty = if self.match_char('=') {
+                    TokenType::LESS_EQUAL
+                } else {
+                    TokenType::LESS
+                };
+                self.add_token(tty)
+            }
+            '>' => {
+                let tty = if self.match_char('=') {
+                    TokenType::GREATER_EQUAL
+                } else {
+                    TokenType::GREATER
+                };
+                self.add_token(tty)
+            }
+            '/' => {
+                if self.match_char('/') {
+                    // Exactly three slashes make a doc comment; `////` is
+                    // an ordinary one, as in Rust.
+                    let doc = self.peek() == '/' && self.peek_next() != '/';
+                    while self.peek() != '\n' && !self.is_at_end() {
+                        self.advance();
                     }
-                }
-                ' ' | '\r' | '\t' => (),
-                '\n' => self.line += 1,
-                'o' if self.match_char('r') => self.add_token(TokenType::OR),
-                n if self.is_digit(n) => {
-                    self.number();
-                }
-                '"' => self.string()?,
-                c if self.is_alpha(c) => self.identifier(),
-                _ => {
-                    return Err(LoxError::new_compile(
-                        String::from("Unexpected character."),
-                        self.line,
-                    ))
+                    if doc {
+                        self.doc_comment();
+                    }
+                } else if self.match_char('*') {
+                    self.block_comment()?
+                } else {
+                    self.add_token(TokenType::SLASH)
                 }
             }
-            self.start = self.current;
+            ' ' | '\r' | '\t' => (),
+            '\n' => self.new_line(),
+            n if self.is_digit(n) => self.number()?,
+            '"' => self.string(false)?,
+            'r' if self.peek() == '"' => {
+                self.advance();
+                self.string(true)?
+            }
+            c if self.is_alpha(c) => self.identifier(),
+            _ => return Err(self.error(Message::UnexpectedCharacter)),
         }
-        self.add_token(TokenType::EOF);
-        Ok(&self.tokens)
+        Ok(())
+    }
+
+    /// Keeps the text of the `///` comment just scanned, without the
+    /// slashes and the space after them.
+    fn doc_comment(&mut self) {
+        let text: String = self.source[self.start + 3..self.current].iter().collect();
+        let text = text.trim_end_matches('\r');
+        self.doc
+            .push(text.strip_prefix(' ').unwrap_or(text).to_string());
+    }
+
+    /// Skips a `/* ... */` comment, which may contain other block comments.
+    /// An unclosed one is reported at its opening `/*`, however far the
+    /// scanner got looking for the end.
+    fn block_comment(&mut self) -> Result<(), LoxError> {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err(self.error_between(
+                    Message::UnterminatedBlockComment,
+                    self.start,
+                    self.start + 2,
+                ));
+            }
+            match self.advance() {
+                '/' if self.match_char('*') => depth += 1,
+                '*' if self.match_char('/') => depth -= 1,
+                '\n' => self.new_line(),
+                _ => (),
+            }
+        }
+        Ok(())
     }
 
     fn identifier(&mut self) {
@@ -147,20 +277,66 @@ impl Scanner {
         }
     }
 
+    /// Scans a number literal, starting from its first digit or, for `.5`,
+    /// its dot. Letters and digits running on from the literal, as in `12ab`
+    /// or `0b102`, are taken as part of it so the whole thing is reported as
+    /// malformed rather than split into a number and a name.
     fn number(&mut self) -> Result<(), LoxError> {
-        while self.is_digit(self.peek()) {
-            self.advance();
-        }
-        if self.peek() == '.' && self.is_digit(self.peek_next()) {
-            self.advance();
-            while self.is_digit(self.peek()) {
+        let decimal = !(self.source[self.start] == '0'
+            && matches!(self.peek(), 'x' | 'X' | 'b' | 'B' | 'o' | 'O'));
+        let mut seen_dot = self.source[self.start] == '.';
+        let mut seen_exponent = false;
+        loop {
+            let c = self.peek();
+            if self.is_alpha_number(c) {
+                self.advance();
+                if decimal && matches!(c, 'e' | 'E') {
+                    seen_exponent = true;
+                    if matches!(self.peek(), '+' | '-') && self.is_digit(self.peek_next()) {
+                        self.advance();
+                    }
+                }
+            } else if decimal
+                && c == '.'
+                && !seen_dot
+                && !seen_exponent
+                && self.is_digit(self.peek_next())
+            {
+                seen_dot = true;
                 self.advance();
+            } else {
+                break;
             }
         }
-        let num: String = self.source[self.start..self.current].iter().collect();
-        let num = num.parse::<f64>().unwrap();
+
+        let text: String = self.source[self.start..self.current].iter().collect();
+        let (num, err) = match parse_number(&text) {
+            Some(num) if num.is_infinite() => (0.0, Some(Message::NumberTooLarge(text))),
+            Some(num) => (num, None),
+            None => (0.0, Some(Message::MalformedNumber(text))),
+        };
+        // A bad literal still becomes a NUMBER, standing in as 0, so the
+        // parser doesn't also report a missing expression.
         self.add_token_object(TokenType::NUMBER, Some(Object::Num(num)));
-        Ok(())
+        match err {
+            Some(msg) => Err(self.error(msg)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the last token can end an expression, making a '.' after it
+    /// a property access, as in `a.5`, rather than the start of `.5`.
+    fn after_operand(&self) -> bool {
+        self.tokens.last().is_some_and(|token| {
+            matches!(
+                token.tty,
+                TokenType::IDENTIFIER
+                    | TokenType::RIGHT_PAREN
+                    | TokenType::THIS
+                    | TokenType::NUMBER
+                    | TokenType::STRING
+            )
+        })
     }
 
     fn peek_next(&self) -> char {
@@ -171,42 +347,239 @@ impl Scanner {
         }
     }
 
-    fn string(&mut self) -> Result<(), LoxError> {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
-            }
+    /// Scans a string whose opening '"' was just consumed: `"..."`, or a
+    /// multi-line `"""..."""` whose common indentation is removed. Escape
+    /// sequences and interpolations are processed unless the string is `raw`
+    /// (prefixed by `r`).
+    fn string(&mut self, raw: bool) -> Result<(), LoxError> {
+        let triple = self.peek() == '"' && self.peek_next() == '"';
+        if triple {
+            self.advance();
             self.advance();
         }
+        self.string_segment(StringLiteral {
+            raw,
+            triple,
+            depth: 0,
+            segments: vec![],
+            tokens: vec![],
+        })
+    }
 
-        if self.is_at_end() {
-            return Err(LoxError::new_compile(
-                String::from("Unterminated string."),
-                self.line,
-            ));
+    /// Scans literal text up to the end of `string` or its next `${`. In the
+    /// latter case an INTERPOLATION token is added and `string` waits in
+    /// `interpolations` for the '}' that resumes it.
+    fn string_segment(&mut self, mut string: StringLiteral) -> Result<(), LoxError> {
+        let content_start = self.current;
+        loop {
+            if self.is_at_end() {
+                // Finish a string that already has interpolations anyway, so
+                // the parser doesn't report its end as missing too.
+                if !string.tokens.is_empty() {
+                    string.segments.push(content_start..self.current);
+                    self.finish_string(string);
+                }
+                return Err(self.error(Message::UnterminatedString));
+            }
+            if string.triple && self.at_triple_quote() || !string.triple && self.peek() == '"' {
+                break;
+            }
+            if !string.raw && self.peek() == '$' && self.peek_next() == '{' {
+                string.segments.push(content_start..self.current);
+                self.advance();
+                self.advance();
+                self.add_token(TokenType::INTERPOLATION);
+                string.tokens.push(self.tokens.len() - 1);
+                self.interpolations.push(string);
+                return Ok(());
+            }
+            let c = self.advance();
+            // Skip whatever is escaped so that `\"` doesn't end the string.
+            let c = if c == '\\' && !string.raw && !self.is_at_end() {
+                self.advance()
+            } else {
+                c
+            };
+            if c == '\n' {
+                self.new_line();
+            }
         }
-        self.advance();
-        let str: String = self.source[self.start + 1..self.current - 1]
+        string.segments.push(content_start..self.current);
+        for _ in 0..if string.triple { 3 } else { 1 } {
+            self.advance();
+        }
+        self.finish_string(string);
+        Ok(())
+    }
+
+    /// Works out the text of every segment of `string` now that all of it is
+    /// known, since dedenting looks at every line. The last segment becomes a
+    /// STRING token.
+    fn finish_string(&mut self, string: StringLiteral) {
+        let segments = if string.triple {
+            self.dedent(&string.segments)
+        } else {
+            string
+                .segments
+                .iter()
+                .map(|segment| segment.clone().collect())
+                .collect()
+        };
+        let mut values: Vec<String> = segments
             .iter()
+            .map(|chars| {
+                if string.raw {
+                    chars.iter().map(|&i| self.source[i]).collect()
+                } else {
+                    self.unescape(chars)
+                }
+            })
             .collect();
-        self.add_token_object(TokenType::STRING, Some(Object::Str(str)));
-        Ok(())
+        let last = values.pop().unwrap_or_default();
+        for (&token, value) in string.tokens.iter().zip(values) {
+            self.tokens[token].literal = Some(Object::Str(value));
+        }
+        self.add_token_object(TokenType::STRING, Some(Object::Str(last)));
+    }
+
+    fn at_triple_quote(&self) -> bool {
+        self.source[self.current..].starts_with(&['"', '"', '"'])
+    }
+
+    /// Dedents the body of a `"""` string, given as the ranges of literal
+    /// text around its interpolations, and returns the indices of the
+    /// characters that remain in each range. A first line holding only
+    /// whitespace is dropped, as is the whitespace before the closing quotes.
+    /// Then the indentation shared by every non-blank line is stripped, not
+    /// counting text that follows the opening quotes directly. A line with an
+    /// interpolation on it is never blank.
+    fn dedent(&self, segments: &[Range<usize>]) -> Vec<Vec<usize>> {
+        // Each line's characters, with `None` where an interpolation sits,
+        // and the newline that ended the line before.
+        let mut lines: Vec<(Option<usize>, Vec<Option<usize>>)> = vec![(None, vec![])];
+        for (n, segment) in segments.iter().enumerate() {
+            if n > 0 {
+                lines.last_mut().unwrap().1.push(None);
+            }
+            for i in segment.clone() {
+                if self.source[i] == '\n' {
+                    lines.push((Some(i), vec![]));
+                } else {
+                    lines.last_mut().unwrap().1.push(Some(i));
+                }
+            }
+        }
+
+        let is_space = |item: &Option<usize>| item.is_some_and(|i| self.source[i].is_whitespace());
+        let is_blank = |items: &[Option<usize>]| items.iter().all(is_space);
+        let starts_on_quote_line = !is_blank(&lines[0].1);
+        if lines.len() > 1 && is_blank(&lines[0].1) {
+            lines.remove(0);
+        }
+        if lines.len() > 1 && lines.last().is_some_and(|line| is_blank(&line.1)) {
+            lines.pop();
+        }
+        let skip = usize::from(starts_on_quote_line);
+        let common = lines
+            .iter()
+            .skip(skip)
+            .filter(|line| !is_blank(&line.1))
+            .map(|line| line.1.iter().take_while(|item| is_space(item)).count())
+            .min()
+            .unwrap_or(0);
+
+        let mut chars = vec![vec![]; segments.len()];
+        let mut segment = 0;
+        for (n, (newline, items)) in lines.iter().enumerate() {
+            if n > 0 {
+                chars[segment].extend(*newline);
+            }
+            if is_blank(items) {
+                continue;
+            }
+            let strip = if n < skip { 0 } else { common };
+            for item in &items[strip..] {
+                match item {
+                    Some(i) => chars[segment].push(*i),
+                    None => segment += 1,
+                }
+            }
+        }
+        chars
+    }
+
+    /// The string spelled by the characters at `chars`, with escape sequences
+    /// replaced. Invalid escapes are reported and left out, and the rest of
+    /// the string is still produced so the parser isn't thrown off.
+    fn unescape(&mut self, chars: &[usize]) -> String {
+        let mut str = String::new();
+        let mut iter = chars.iter().copied().peekable();
+        while let Some(i) = iter.next() {
+            if self.source[i] != '\\' {
+                str.push(self.source[i]);
+                continue;
+            }
+            let Some(j) = iter.next() else {
+                break;
+            };
+            match self.source[j] {
+                'n' => str.push('\n'),
+                't' => str.push('\t'),
+                'r' => str.push('\r'),
+                '0' => str.push('\0'),
+                '"' => str.push('"'),
+                '\\' => str.push('\\'),
+                '$' => str.push('$'),
+                'u' => {
+                    let mut end = j + 1;
+                    let mut digits = String::new();
+                    if iter.next_if(|&k| self.source[k] == '{').is_some() {
+                        end += 1;
+                        while let Some(k) = iter.next_if(|&k| self.source[k].is_ascii_hexdigit()) {
+                            digits.push(self.source[k]);
+                            end = k + 1;
+                        }
+                        if let Some(k) = iter.next_if(|&k| self.source[k] == '}') {
+                            end = k + 1;
+                            let c = u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .and_then(char::from_u32);
+                            if let (1..=6, Some(c)) = (digits.len(), c) {
+                                str.push(c);
+                                continue;
+                            }
+                        }
+                    }
+                    let seq: String = self.source[i..end].iter().collect();
+                    let err = self.error_between(Message::InvalidUnicodeEscape(seq), i, end);
+                    self.errors.push(err);
+                }
+                _ => {
+                    let seq: String = self.source[i..=j].iter().collect();
+                    let err = self.error_between(Message::InvalidEscape(seq), i, j + 1);
+                    self.errors.push(err);
+                }
+            }
+        }
+        str
     }
 
     fn is_digit(&self, c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 
+    /// Identifiers follow Unicode's XID rules, as Rust's do, so `größe` and
+    /// `变量` are names too. `_` is added since XID_Start lacks it.
     fn is_alpha(&self, c: char) -> bool {
-        c >= 'a' && c <= 'z' || c >= 'A' && c <= 'Z' || c == '_'
+        c == '_' || c.is_xid_start()
     }
 
     fn is_alpha_number(&self, c: char) -> bool {
-        self.is_alpha(c) || self.is_digit(c)
+        c.is_xid_continue()
     }
 
     fn match_char(&mut self, expected: char) -> bool {
@@ -217,7 +590,8 @@ impl Scanner {
             return false;
         }
         self.current += 1;
-        return true;
+        self.offset += expected.len_utf8();
+        true
     }
 
     fn peek(&self) -> char {
@@ -231,6 +605,7 @@ impl Scanner {
     fn advance(&mut self) -> char {
         let result = *self.source.get(self.current).unwrap();
         self.current += 1;
+        self.offset += result.len_utf8();
         result
     }
     fn add_token(&mut self, tty: TokenType) {
@@ -238,6 +613,223 @@ impl Scanner {
     }
     fn add_token_object(&mut self, tty: TokenType, literal: Option<Object>) {
         let str: String = self.source[self.start..self.current].iter().collect();
-        self.tokens.push(Token::new(tty, str, literal, self.line))
+        let mut token = Token::new(
+            tty,
+            str,
+            literal,
+            self.start_line,
+            self.start_column,
+            self.span(self.start_offset, self.offset),
+        );
+        if !self.doc.is_empty() {
+            token.doc = Some(std::mem::take(&mut self.doc).join("\n"));
+        }
+        self.tokens.push(token)
+    }
+
+    /// Called after consuming a '\n' so columns restart on the next line.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
+    /// An error covering the text scanned so far for the current token.
+    fn error(&self, msg: Message) -> LoxError {
+        LoxError::new_scan(
+            msg,
+            self.start_line,
+            self.start_column,
+            self.span(self.start_offset, self.offset),
+        )
+    }
+
+    /// An error covering the characters `from..to`, which needn't belong to
+    /// the current token.
+    fn error_between(&self, msg: Message, from: usize, to: usize) -> LoxError {
+        let before = &self.source[..from];
+        let line = 1 + before.iter().filter(|&&c| c == '\n').count();
+        let column = match before.iter().rposition(|&c| c == '\n') {
+            Some(newline) => from - newline,
+            None => from + 1,
+        };
+        let byte_len = |chars: &[char]| chars.iter().map(|c| c.len_utf8()).sum::<usize>();
+        let start = byte_len(before);
+        let end = start + byte_len(&self.source[from..to]);
+        LoxError::new_scan(msg, line, column, self.span(start, end))
+    }
+
+    /// A span over the bytes `start..end` of this source.
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span {
+            start,
+            end,
+            source: self.source_id,
+        }
+    }
+
+    fn column(&self, index: usize) -> usize {
+        index - self.line_start + 1
+    }
+}
+
+/// The value of a number literal as scanned by `Scanner::number`, or `None`
+/// if it's malformed. Separators must sit between two digits, so `1_000` is
+/// fine but `1__000`, `1_` and `0x_FF` are not.
+fn parse_number(text: &str) -> Option<f64> {
+    let (radix, digits) = match text.get(..2) {
+        Some("0x" | "0X") => (16, &text[2..]),
+        Some("0b" | "0B") => (2, &text[2..]),
+        Some("0o" | "0O") => (8, &text[2..]),
+        _ => (10, text),
+    };
+    let chars: Vec<char> = digits.chars().collect();
+    let separated = |i: usize| {
+        i > 0 && i + 1 < chars.len() && chars[i - 1].is_digit(radix) && chars[i + 1].is_digit(radix)
+    };
+    if !(0..chars.len()).all(|i| chars[i] != '_' || separated(i)) {
+        return None;
+    }
+    let digits: String = chars.into_iter().filter(|&c| c != '_').collect();
+
+    if radix == 10 {
+        // The scanner only hands over text starting with a digit or a dot,
+        // so Rust's float syntax can't let "inf" or "NaN" through.
+        return digits.parse().ok();
+    }
+    if digits.is_empty() {
+        return None;
+    }
+    digits.chars().try_fold(0.0, |value, c| {
+        Some(value * f64::from(radix) + f64::from(c.to_digit(radix)?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text of every STRING and INTERPOLATION token scanned from
+    /// `source`, in order.
+    fn strings(source: &str) -> Vec<String> {
+        let (tokens, errors) = Scanner::new().scan_tokens(source.to_string(), 0);
+        assert!(errors.is_empty(), "{errors:?}");
+        tokens
+            .into_iter()
+            .filter(|token| matches!(token.tty, TokenType::STRING | TokenType::INTERPOLATION))
+            .map(|token| match token.literal {
+                Some(Object::Str(value)) => value,
+                literal => panic!("{:?} has literal {literal:?}", token.lexeme),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dedent_drops_blank_first_and_last_lines() {
+        let source = "\"\"\"\n    one\n\n      two\n    \"\"\"";
+        assert_eq!(strings(source), ["one\n\n  two"]);
+    }
+
+    #[test]
+    fn dedent_keeps_text_after_the_opening_quotes() {
+        let source = "\"\"\"Report:\n      - one\n      - two\n    \"\"\"";
+        assert_eq!(strings(source), ["Report:\n- one\n- two"]);
+    }
+
+    #[test]
+    fn dedent_counts_indentation_before_an_interpolation() {
+        let source = "\"\"\"\n    ${n} items\n      left\n    \"\"\"";
+        assert_eq!(strings(source), ["", " items\n  left"]);
+    }
+
+    #[test]
+    fn dedent_keeps_a_line_holding_only_an_interpolation() {
+        let source = "\"\"\"\n  a\n  ${b}\n  \"\"\"";
+        assert_eq!(strings(source), ["a\n", ""]);
+    }
+
+    #[test]
+    fn escape_errors_come_before_errors_in_later_interpolations() {
+        for source in [
+            "print \"\\q ${@1}\";",
+            "print \"\"\"\n  \\q ${@1}\n  \"\"\";",
+        ] {
+            let (_, errors) = Scanner::new().scan_tokens(source.to_string(), 0);
+            let messages: Vec<_> = errors
+                .iter()
+                .map(|err| match err {
+                    LoxError::Scan(err) => err.msg.clone(),
+                    err => panic!("{err}"),
+                })
+                .collect();
+            assert!(
+                matches!(
+                    messages[..],
+                    [Message::InvalidEscape(_), Message::UnexpectedCharacter]
+                ),
+                "{source}: {messages:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn dot_before_a_digit_is_a_property_access_after_an_operand() {
+        let (tokens, _) = Scanner::new().scan_tokens("a.5 (b).5 = .5".to_string(), 0);
+        let types: Vec<_> = tokens.iter().map(|token| token.tty).collect();
+        assert!(
+            matches!(
+                types[..],
+                [
+                    TokenType::IDENTIFIER,
+                    TokenType::DOT,
+                    TokenType::NUMBER,
+                    TokenType::LEFT_PAREN,
+                    TokenType::IDENTIFIER,
+                    TokenType::RIGHT_PAREN,
+                    TokenType::DOT,
+                    TokenType::NUMBER,
+                    TokenType::EQUAL,
+                    TokenType::NUMBER,
+                    TokenType::EOF
+                ]
+            ),
+            "{types:?}"
+        );
+    }
+
+    #[test]
+    fn parse_number_allows_separators_between_digits() {
+        assert_eq!(parse_number("1_000"), Some(1000.0));
+        assert_eq!(parse_number("1_000.5_5"), Some(1000.55));
+        assert_eq!(parse_number("0xFF_FF"), Some(65535.0));
+        for text in ["1__000", "1_", "1_.5", "1._5", "0x_FF", "0b1_"] {
+            assert_eq!(parse_number(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn parse_number_reads_prefixed_literals() {
+        assert_eq!(parse_number("0xff"), Some(255.0));
+        assert_eq!(parse_number("0XfF"), Some(255.0));
+        assert_eq!(parse_number("0b101"), Some(5.0));
+        assert_eq!(parse_number("0o17"), Some(15.0));
+        for text in ["0x", "0b", "0o", "0b102", "0o8", "0xfg", "12ab"] {
+            assert_eq!(parse_number(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn parse_number_overflows_to_infinity() {
+        let digits = "f".repeat(300);
+        assert_eq!(parse_number(&format!("0x{digits}")), Some(f64::INFINITY));
+        assert_eq!(parse_number("1e400"), Some(f64::INFINITY));
+        assert_eq!(parse_number("1e308"), Some(1e308));
+    }
+
+    #[test]
+    fn malformed_number_still_scans_as_a_number() {
+        let (tokens, errors) = Scanner::new().scan_tokens("print 0b2;".to_string(), 0);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(tokens[1].tty, TokenType::NUMBER));
+        assert!(matches!(tokens[1].literal, Some(Object::Num(n)) if n == 0.0));
     }
 }
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+/// A half-open range of byte offsets into the source text. Every token and
+/// syntax tree node carries one, so later stages can point back at exactly
+/// the code they came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    /// Which source text the offsets are into, as numbered by `Lox` for each
+    /// piece of code it compiles. 0 when there is none, as for a syntax tree
+    /// loaded from JSON. A function can run long after the REPL line or file
+    /// that defined it, so errors can't assume the code at hand is the
+    /// right text.
+    #[serde(skip)]
+    pub source: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span {
+            start,
+            end,
+            source: 0,
+        }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            source: self.source,
+        }
+    }
+
+    pub fn len(self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
+    /// The slice of `source` this span covers, or "" if the span doesn't
+    /// belong to `source`.
+    pub fn text(self, source: &str) -> &str {
+        source.get(self.start..self.end).unwrap_or("")
+    }
+
+    /// 1-based line and column of the span's start. Columns count characters,
+    /// not bytes.
+    pub fn line_column(self, source: &str) -> (usize, usize) {
+        position(source, self.start)
+    }
+
+    /// 1-based line and column just past the span's last character.
+    pub fn end_line_column(self, source: &str) -> (usize, usize) {
+        position(source, self.end)
+    }
+}
+
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = source.get(..offset).unwrap_or(source);
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
@@ -1,6 +1,11 @@
-use std::fmt;
+use std::{fmt, rc::Rc};
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{callable::LoxCallable, class::LoxClass, instance::LoxInstance, span::Span};
+
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum TokenType {
     // Single-character tokens.
     LEFT_PAREN,  // (
@@ -28,6 +33,9 @@ pub enum TokenType {
     // Literals.
     IDENTIFIER,
     STRING,
+    /// The part of a string before a `${`, or between a `}` and the next
+    /// `${`. The string's last part is a STRING.
+    INTERPOLATION,
     NUMBER,
 
     // Keywords.
@@ -51,39 +59,142 @@ pub enum TokenType {
     EOF,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum Object {
     Num(f64),
     Str(String),
     Nil,
-    True,
-    False,
+    Bool(bool),
+    Callable(Rc<dyn LoxCallable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
 }
 
-#[derive(Debug, Clone)]
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Num(a), Object::Num(b)) => a == b,
+            (Object::Str(a), Object::Str(b)) => a == b,
+            (Object::Nil, Object::Nil) => true,
+            (Object::Bool(a), Object::Bool(b)) => a == b,
+            (Object::Callable(a), Object::Callable(b)) => Rc::ptr_eq(a, b),
+            (Object::Class(a), Object::Class(b)) => Rc::ptr_eq(a, b),
+            (Object::Instance(a), Object::Instance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Only the literal values a program can spell out serialize, as the JSON
+/// number, string, `null` or boolean they correspond to. Functions, classes
+/// and instances exist only at runtime.
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Object::Num(n) => serializer.serialize_f64(*n),
+            Object::Str(s) => serializer.serialize_str(s),
+            Object::Nil => serializer.serialize_unit(),
+            Object::Bool(b) => serializer.serialize_bool(*b),
+            _ => Err(ser::Error::custom("only literal values can be serialized")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(LiteralVisitor)
+    }
+}
+
+struct LiteralVisitor;
+
+impl de::Visitor<'_> for LiteralVisitor {
+    type Value = Object;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a number, string, boolean or null")
+    }
+
+    fn visit_f64<E: de::Error>(self, n: f64) -> Result<Object, E> {
+        Ok(Object::Num(n))
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Object, E> {
+        Ok(Object::Num(n as f64))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Object, E> {
+        Ok(Object::Num(n as f64))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Object, E> {
+        Ok(Object::Str(s.to_string()))
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Object, E> {
+        Ok(Object::Bool(b))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Nil)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Nil)
+    }
+}
+
+/// In a serialized syntax tree the position fields are optional, so tools
+/// generating trees without source text can leave them out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Token {
     pub tty: TokenType,
     pub lexeme: String,
+    /// Only the scanner and parser need this; the tree keeps the value in
+    /// `LiteralExpr` instead.
+    #[serde(skip)]
     pub literal: Option<Object>,
+    #[serde(default)]
     pub line: usize,
+    #[serde(default)]
+    pub column: usize,
+    #[serde(default)]
+    pub span: Span,
+    /// The `///` comments right before the token. The parser moves them onto
+    /// the declaration the token starts, so the tree doesn't repeat them.
+    #[serde(skip)]
+    pub doc: Option<String>,
 }
 
 impl Token {
-    pub fn new(tty: TokenType, lexeme: String, literal: Option<Object>, line: usize) -> Token {
-        return Token {
+    pub fn new(
+        tty: TokenType,
+        lexeme: String,
+        literal: Option<Object>,
+        line: usize,
+        column: usize,
+        span: Span,
+    ) -> Token {
+        Token {
             tty,
             lexeme,
             line,
+            column,
             literal,
-        };
+            span,
+            doc: None,
+        }
     }
-    pub fn eof(line: usize) -> Token {
-        return Token {
+    pub fn eof(line: usize, column: usize, span: Span) -> Token {
+        Token {
             tty: TokenType::EOF,
             lexeme: String::from(""),
             literal: None,
             line,
-        };
+            column,
+            span,
+            doc: None,
+        }
     }
 }
 